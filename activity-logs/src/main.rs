@@ -0,0 +1,102 @@
+use clap::Parser;
+use futures::{pin_mut, StreamExt, TryStreamExt};
+use std::process::ExitCode;
+
+/// Follow the log of an activity, e.g. a deploy, as it is written
+#[derive(Parser, Debug)]
+struct Args {
+    /// Project ID
+    #[arg(long, short)]
+    project: String,
+
+    /// Environment ID, to follow its most recent activity
+    #[arg(long, short, default_value = "main")]
+    environment: String,
+
+    /// Activity ID, instead of the environment's most recent activity
+    activity: Option<String>,
+
+    /// Platform Access Token
+    #[arg(long, env = "PLATFORMSH_CLI_TOKEN")]
+    token: String,
+
+    /// API base url
+    #[arg(
+        long,
+        env = "PLATFORMSH_API_URL",
+        default_value = "https://api.platform.sh"
+    )]
+    api_url: String,
+
+    /// OAuth2 base url
+    #[arg(
+        long,
+        env = "PLATFORMSH_AUTH_URL",
+        default_value = "https://auth.api.platform.sh"
+    )]
+    auth_url: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+
+    let client = platform::ApiClient::builder()
+        .api_url(&args.api_url)
+        .auth_url(&args.auth_url)
+        .build(&args.token)
+        .await?;
+
+    let activity_id = match args.activity {
+        Some(activity_id) => activity_id,
+        None => {
+            let filter = platform::ActivityFilter {
+                page_size: Some(1),
+                ..Default::default()
+            };
+            let activities =
+                client.activities_stream(&args.project, Some(&args.environment), &filter);
+            pin_mut!(activities);
+            match activities.try_next().await? {
+                Some(activity) => activity.id,
+                None => {
+                    eprintln!("No activities on {}", args.environment);
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+        }
+    };
+
+    let records = client
+        .activity_log_stream(&args.project, &activity_id)
+        .await?;
+    pin_mut!(records);
+    while let Some(record) = records.next().await {
+        let record = record?;
+        if record.seal {
+            break;
+        }
+        match record.timestamp {
+            Some(timestamp) => println!("{} {}", timestamp.format("%H:%M:%S"), record.message),
+            None => println!("{}", record.message),
+        }
+    }
+
+    let activity = client.activity(&args.project, &activity_id).await?;
+    eprintln!(
+        "{} {} {}",
+        activity.r#type,
+        activity.state,
+        activity.result.as_deref().unwrap_or("")
+    );
+
+    Ok(if activity.is_success() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
@@ -0,0 +1,57 @@
+use platform_mock::{MockServer, Seed};
+use tokio::process::Command;
+
+const SEED: &str = r#"
+projects:
+  project1:
+    environments:
+      - { name: main, is_main: true }
+    activities:
+      - id: push1
+        type: environment.push
+        environments: [main]
+        result: failure
+        created_at: "2024-05-01T10:00:00Z"
+        log: ["Building", "E: composer install failed"]
+      - id: push2
+        type: environment.push
+        environments: [main]
+        created_at: "2024-05-02T10:00:00Z"
+        log: ["Building", "Deploying", "Done"]
+        log_interrupt_after: 2
+"#;
+
+async fn activity_logs(server: &MockServer, args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_activity-logs"))
+        .args(args)
+        .env("PLATFORMSH_API_URL", server.url())
+        .env("PLATFORMSH_AUTH_URL", server.url())
+        .env("PLATFORMSH_CLI_TOKEN", "api-token")
+        .env("TZ", "UTC")
+        .output()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn follows_latest_activity() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+
+    let output = activity_logs(&server, &["-p", "project1"]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        stdout,
+        "10:00:00 Building\n10:00:01 Deploying\n10:00:02 Done\n"
+    );
+}
+
+#[tokio::test]
+async fn fails_with_activity() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+
+    let output = activity_logs(&server, &["-p", "project1", "push1"]).await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("composer install failed"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("environment.push complete failure"));
+}
@@ -0,0 +1,144 @@
+use platform::ApiClient;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::source::{Level, SourceVariable};
+
+/// What happened to a single variable
+#[derive(Debug)]
+pub enum Outcome {
+    Created,
+    Updated,
+    Skipped(&'static str),
+    /// The value couldn't be read from the source runtime
+    Unreadable(String),
+    Failed(platform::Error),
+}
+
+impl Outcome {
+    pub fn is_failure(&self) -> bool {
+        matches!(self, Outcome::Failed(_) | Outcome::Unreadable(_))
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Created => write!(f, "created"),
+            Outcome::Updated => write!(f, "updated"),
+            Outcome::Skipped(reason) => write!(f, "skipped ({})", reason),
+            Outcome::Unreadable(error) => write!(f, "failed: {}", error),
+            Outcome::Failed(error) => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+/// Names of the variables already on the destination, listed once up front
+/// rather than for every variable applied
+#[derive(Debug, Default)]
+pub struct Existing {
+    project: HashSet<String>,
+    /// Set on the environment itself, not inherited
+    environments: HashMap<String, HashSet<String>>,
+}
+
+impl Existing {
+    /// The project variables of `destination`, and the environment variables
+    /// of every environment `variables` are on
+    pub async fn list(
+        client: &ApiClient,
+        destination: &str,
+        variables: &[SourceVariable],
+    ) -> Result<Existing, platform::Error> {
+        let mut existing = Existing::default();
+        if variables.iter().any(|v| v.level == Level::Project) {
+            existing.project = client
+                .project_variables(destination)
+                .await?
+                .into_iter()
+                .map(|v| v.name)
+                .collect();
+        }
+        for v in variables.iter() {
+            let Level::Environment(environment) = &v.level else {
+                continue;
+            };
+            if existing.environments.contains_key(environment) {
+                continue;
+            }
+            let names = client
+                .environment_variables(destination, environment)
+                .await?
+                .into_iter()
+                .filter(|v| !v.inherited)
+                .map(|v| v.name)
+                .collect();
+            existing.environments.insert(environment.clone(), names);
+        }
+        Ok(existing)
+    }
+
+    fn contains(&self, level: &Level, name: &str) -> bool {
+        match level {
+            Level::Project => self.project.contains(name),
+            Level::Environment(environment) => self
+                .environments
+                .get(environment)
+                .is_some_and(|names| names.contains(name)),
+        }
+    }
+}
+
+/// Create or update `variable` on the `destination` project, depending on
+/// whether it is one of the `existing` ones. Variables that only exist
+/// through inheritance on the destination get their own copy at the
+/// environment level.
+pub async fn apply(
+    client: &ApiClient,
+    destination: &str,
+    existing: &Existing,
+    variable: &SourceVariable,
+) -> Outcome {
+    if variable.inherited {
+        return Outcome::Skipped("inherited");
+    }
+    if let Some(error) = &variable.runtime_error {
+        return Outcome::Unreadable(error.clone());
+    }
+    if variable.value_unknown {
+        return Outcome::Skipped("sensitive value must be set separately");
+    }
+
+    let request = &variable.request;
+    let exists = existing.contains(&variable.level, &request.name);
+    let result = match &variable.level {
+        Level::Project => {
+            if exists {
+                client
+                    .update_project_variable(destination, request)
+                    .await
+                    .map(|_| Outcome::Updated)
+            } else {
+                client
+                    .create_project_variable(destination, request)
+                    .await
+                    .map(|_| Outcome::Created)
+            }
+        }
+        Level::Environment(environment) => {
+            if exists {
+                client
+                    .update_environment_variable(destination, environment, request)
+                    .await
+                    .map(|_| Outcome::Updated)
+            } else {
+                client
+                    .create_environment_variable(destination, environment, request)
+                    .await
+                    .map(|_| Outcome::Created)
+            }
+        }
+    };
+
+    result.unwrap_or_else(Outcome::Failed)
+}
@@ -0,0 +1,70 @@
+use age::secrecy::SecretString;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Start of every binary age file
+const MAGIC: &[u8] = b"age-encryption.org/";
+
+/// Who can open an export
+pub enum Key {
+    Passphrase(SecretString),
+    /// `age1...` public keys for export, identity files for import
+    Recipients(Vec<String>),
+    Identities(Vec<PathBuf>),
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn encrypt(plaintext: &[u8], key: &Key) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let encryptor = match key {
+        Key::Passphrase(passphrase) => age::Encryptor::with_user_passphrase(passphrase.clone()),
+        Key::Recipients(recipients) => {
+            let recipients = recipients
+                .iter()
+                .map(|r| {
+                    age::x25519::Recipient::from_str(r)
+                        .map_err(|error| format!("recipient {}: {}", r, error))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))?
+        }
+        Key::Identities(_) => return Err("identities can only decrypt".into()),
+    };
+
+    let mut encrypted = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(plaintext)?;
+    writer.finish()?;
+    Ok(encrypted)
+}
+
+pub fn decrypt(encrypted: &[u8], key: &Key) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let identities: Vec<Box<dyn age::Identity>> = match key {
+        Key::Passphrase(passphrase) => {
+            vec![Box::new(age::scrypt::Identity::new(passphrase.clone()))]
+        }
+        Key::Identities(files) => {
+            let mut identities = Vec::new();
+            for file in files {
+                identities.extend(identity_file(file)?);
+            }
+            identities
+        }
+        Key::Recipients(_) => return Err("recipients can only encrypt".into()),
+    };
+
+    let decryptor = age::Decryptor::new(encrypted)?;
+    let mut reader = decryptor.decrypt(identities.iter().map(|i| i.as_ref()))?;
+    let mut plaintext = Vec::new();
+    reader.read_to_end(&mut plaintext)?;
+    Ok(plaintext)
+}
+
+fn identity_file(path: &Path) -> Result<Vec<Box<dyn age::Identity>>, Box<dyn std::error::Error>> {
+    let file = age::IdentityFile::from_file(path.to_string_lossy().into_owned())
+        .map_err(|error| format!("{}: {}", path.display(), error))?;
+    Ok(file.into_identities()?)
+}
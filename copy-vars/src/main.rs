@@ -1,14 +1,33 @@
-use clap::Parser;
-use std::process::{Command, Stdio};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+mod apply;
+mod crypt;
+mod plan;
+mod plan_file;
+mod runtime;
+mod shell;
+mod source;
+
+use crate::crypt::Key;
+use crate::plan_file::PlanFile;
+use crate::runtime::{PlatformCli, RuntimeReader};
+use crate::shell::quote;
+use crate::source::{Level, SourceVariable};
 
 #[derive(Parser, Debug)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Project ID
-    #[arg(long, short)]
-    project: String,
+    #[arg(long, short, required = true)]
+    project: Option<String>,
 
-    #[arg(long, short)]
-    destination: String,
+    #[arg(long, short, required = true)]
+    destination: Option<String>,
 
     /// Platform Access Token
     #[arg(long, env = "PLATFORMSH_CLI_TOKEN")]
@@ -21,121 +40,362 @@ struct Args {
     /// App - which app to ssh into
     #[arg(long, short = 'A')]
     app: Option<String>,
+
+    /// Create or update the variables on the destination through the API,
+    /// instead of printing `platform` commands
+    #[arg(long, action)]
+    apply: bool,
+
+    /// With --apply, redeploy each destination environment afterwards
+    #[arg(long, action, requires = "apply")]
+    redeploy: bool,
+
+    /// Print how the destination variables differ from the source, without
+    /// changing anything
+    #[arg(long, action, conflicts_with = "apply")]
+    plan: bool,
+
+    /// How to print the variables when neither --apply nor --plan is given
+    #[arg(long, value_enum, default_value_t = Format::Script)]
+    format: Format,
+
+    /// API base url
+    #[arg(
+        long,
+        env = "PLATFORMSH_API_URL",
+        default_value = "https://api.platform.sh"
+    )]
+    api_url: String,
+
+    /// OAuth2 base url
+    #[arg(
+        long,
+        env = "PLATFORMSH_AUTH_URL",
+        default_value = "https://auth.api.platform.sh"
+    )]
+    auth_url: String,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write the variables of a project, values included, to an encrypted
+    /// file for `copy-vars import`
+    Export {
+        /// Project ID
+        #[arg(long, short)]
+        project: String,
+
+        /// Environment ID
+        #[arg(long, short, default_value = "main")]
+        environment: Vec<String>,
+
+        /// App - which app to ssh into
+        #[arg(long, short = 'A')]
+        app: Option<String>,
+
+        /// Encrypted file to write
+        #[arg(long, short)]
+        out: PathBuf,
+
+        /// Encrypt to this age public key (`age1...`) instead of a
+        /// passphrase. Can be repeated.
+        #[arg(long, short)]
+        recipient: Vec<String>,
+
+        /// Encrypt with a passphrase, taken from $COPY_VARS_PASSPHRASE or
+        /// else the first line of stdin
+        #[arg(long, action, conflicts_with = "recipient")]
+        passphrase: bool,
+    },
+
+    /// Create or update the variables of a plan written with --format json,
+    /// or of an export
+    Import {
+        /// Plan file or encrypted export
+        file: PathBuf,
+
+        /// Destination project, instead of the one in the plan
+        #[arg(long, short)]
+        project: Option<String>,
+
+        /// age identity file to decrypt an export with. Can be repeated.
+        #[arg(long, short)]
+        identity: Vec<PathBuf>,
+
+        /// Decrypt an export with a passphrase, taken from
+        /// $COPY_VARS_PASSPHRASE or else the first line of stdin
+        #[arg(long, action, conflicts_with = "identity")]
+        passphrase: bool,
+
+        /// Redeploy each destination environment afterwards
+        #[arg(long, action)]
+        redeploy: bool,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    /// `platform` CLI commands
+    Script,
+    /// A plan file for `copy-vars import`
+    Json,
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
     let args = Args::parse();
-    let client = platform::ApiClient::new(&args.token).await?;
-
-    let variables: Vec<platform::Variable> = client
-        .get(format!(
-            "https://api.platform.sh/projects/{}/variables",
-            args.project
-        ))
-        .send()
-        .await?
-        .json()
+    let client = platform::ApiClient::builder()
+        .api_url(&args.api_url)
+        .auth_url(&args.auth_url)
+        .build(&args.token)
         .await?;
 
-    for v in variables {
-        let value = if let Some(value) = v.value {
-            value
-        } else if v.visible_runtime && v.name.starts_with("env:") {
-            let mut params = vec!["ssh", "-p", &args.project, "-e", &args.environment[0]];
-            if let Some(ref app) = args.app {
-                params.push("-A");
-                params.push(app);
+    match &args.command {
+        Some(Command::Export {
+            project,
+            environment,
+            app,
+            out,
+            recipient,
+            passphrase,
+        }) => {
+            let key = if !recipient.is_empty() {
+                Key::Recipients(recipient.clone())
+            } else if *passphrase || passphrase_in_env() {
+                Key::Passphrase(read_passphrase()?.into())
+            } else {
+                return Err("export needs --recipient or --passphrase".into());
+            };
+            let reader = PlatformCli { app: app.clone() };
+            let variables = source::collect(&client, Some(&reader), project, environment).await?;
+            let unreadable = report_runtime_errors(&variables);
+            let count = variables.len();
+            let plan = PlanFile::new(project, None, environment, variables);
+            std::fs::write(out, crypt::encrypt(&plan.to_vec()?, &key)?)?;
+            println!("exported {} variables to {}", count, out.display());
+            return Ok(exit_code(unreadable));
+        }
+        Some(Command::Import {
+            file,
+            project,
+            identity,
+            passphrase,
+            redeploy,
+        }) => {
+            let mut data = std::fs::read(file)?;
+            if crypt::is_encrypted(&data) {
+                let key = if !identity.is_empty() {
+                    Key::Identities(identity.clone())
+                } else if *passphrase || passphrase_in_env() {
+                    Key::Passphrase(read_passphrase()?.into())
+                } else {
+                    return Err(format!(
+                        "{} is encrypted, use --identity or --passphrase",
+                        file.display()
+                    )
+                    .into());
+                };
+                data = crypt::decrypt(&data, &key)?;
             }
-            let echo = format!("echo -n ${}", v.name.strip_prefix("env:").unwrap());
-            params.push(&echo);
-
-            let output = Command::new("platform")
-                .args(params)
-                .stdout(Stdio::piped())
-                .output()?;
-
-            String::from_utf8(output.stdout)?
-            //"foobar".to_string()
-        } else {
-            "".to_string()
-        };
-        if v.is_sensitive && !v.visible_runtime {
-            println!("# {} must be found seperately", v.name);
+            let plan = PlanFile::from_slice(&data)?;
+            let Some(destination) = project.as_deref().or(plan.destination.as_deref()) else {
+                return Err(format!("{} has no destination, use --project", file.display()).into());
+            };
+            return apply(
+                &client,
+                destination,
+                &plan.environments,
+                *redeploy,
+                &plan.variables,
+            )
+            .await;
+        }
+        None => {}
+    }
+
+    // Required unless there is a subcommand
+    let project = args.project.as_deref().unwrap();
+    let destination = args.destination.as_deref().unwrap();
+
+    // Planning doesn't compare sensitive values, so it has no reason to ssh
+    // into the source environments
+    let reader = PlatformCli {
+        app: args.app.clone(),
+    };
+    let reader: Option<&dyn RuntimeReader> = if args.plan { None } else { Some(&reader) };
+    let variables = source::collect(&client, reader, project, &args.environment).await?;
+
+    if args.plan {
+        for entry in plan::plan(&client, destination, &args.environment, &variables).await? {
+            println!("{}", entry);
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if args.apply {
+        return apply(
+            &client,
+            destination,
+            &args.environment,
+            args.redeploy,
+            &variables,
+        )
+        .await;
+    }
+
+    let unreadable = report_runtime_errors(&variables);
+    match args.format {
+        Format::Script => print_commands(destination, &args.environment, &variables),
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&PlanFile::new(
+                project,
+                Some(destination),
+                &args.environment,
+                variables
+            ))?
+        ),
+    }
+    Ok(exit_code(unreadable))
+}
+
+/// Tell about every value that couldn't be read at runtime, returning
+/// whether there were any
+fn report_runtime_errors(variables: &[SourceVariable]) -> bool {
+    let mut any = false;
+    for v in variables.iter() {
+        if let Some(error) = &v.runtime_error {
+            eprintln!("{} {}: {}", v.level, v.request.name, error);
+            any = true;
+        }
+    }
+    any
+}
+
+const PASSPHRASE_VAR: &str = "COPY_VARS_PASSPHRASE";
+
+fn passphrase_in_env() -> bool {
+    std::env::var_os(PASSPHRASE_VAR).is_some()
+}
+
+/// The age passphrase, from the environment or else stdin, never from the
+/// command line where it would show up in `ps` and shell history
+fn read_passphrase() -> Result<String, Box<dyn std::error::Error>> {
+    use std::io::IsTerminal;
+
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_VAR) {
+        return Ok(passphrase);
+    }
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        eprint!("Passphrase: ");
+    }
+    let mut line = String::new();
+    stdin.read_line(&mut line)?;
+    let passphrase = line.trim_end_matches(['\r', '\n']);
+    if passphrase.is_empty() {
+        return Err("empty passphrase".into());
+    }
+    Ok(passphrase.to_string())
+}
+
+fn exit_code(failed: bool) -> ExitCode {
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Print `platform` CLI commands recreating the variables on the destination
+fn print_commands(destination: &str, environments: &[String], variables: &[SourceVariable]) {
+    for v in variables.iter().filter(|v| v.level == Level::Project) {
+        let r = &v.request;
+        if let Some(error) = &v.runtime_error {
+            println!("# {} could not be read: {}", r.name, error);
+            continue;
+        }
+        if r.is_sensitive && !r.visible_runtime {
+            println!("# {} must be found seperately", r.name);
             println!("# ");
         }
         println!(
-            "platform variable:create --no-wait --yes --level=project --project={} --name='{}' --value='{}' --json={} --sensitive={} --visible-build={} --visible-runtime={}",
-            args.destination,
-            v.name,
-            value,
-            v.is_json,
-            v.is_sensitive,
-            v.visible_build,
-            v.visible_runtime
+            "platform variable:create --no-wait --yes --level=project --project={} --name={} --value={} --json={} --sensitive={} --visible-build={} --visible-runtime={}",
+            destination,
+            quote(&r.name),
+            quote(&r.value),
+            r.is_json,
+            r.is_sensitive,
+            r.visible_build,
+            r.visible_runtime
         );
     }
 
-    for environment in args.environment {
-        let variables: Vec<platform::EnvironmentVariable> = client
-            .get(format!(
-                "https://api.platform.sh/projects/{}/environments/{}/variables",
-                args.project, environment
-            ))
-            .send()
-            .await?
-            .json()
-            .await?;
-
-        for v in variables {
+    for environment in environments.iter() {
+        let level = Level::Environment(environment.clone());
+        for v in variables.iter().filter(|v| v.level == level) {
+            let r = &v.request;
             if v.inherited {
-                println!("# {} inherited", v.name);
+                println!("# {} inherited", r.name);
+                continue;
+            }
+            if let Some(error) = &v.runtime_error {
+                println!("# {} could not be read: {}", r.name, error);
                 continue;
             }
-            let value = if let Some(value) = v.value {
-                value
-            } else if v.visible_runtime && v.name.starts_with("env:") {
-                let mut params = vec!["ssh", "-p", &args.project, "-e", &v.environment];
-                if let Some(ref app) = args.app {
-                    params.push("-A");
-                    params.push(app);
-                }
-                let echo = format!("echo -n ${}", v.name.strip_prefix("env:").unwrap());
-                params.push(&echo);
-
-                let output = Command::new("platform")
-                    .args(params)
-                    .stdout(Stdio::piped())
-                    .output()?;
-
-                String::from_utf8(output.stdout)?
-                //"foobar".to_string()
-            } else {
-                "".to_string()
-            };
             // println!("# value: \"{}\"", value);
-            if v.is_sensitive && !v.visible_runtime {
+            if r.is_sensitive && !r.visible_runtime {
                 println!("# ");
             }
             println!(
-                "platform variable:create --no-wait --yes --level=environment --project={} --environment={} --name='{}' --value='{}' --json={} --sensitive={} --visible-build={} --visible-runtime={} --enabled={} --inheritable={}",
-                args.destination,
-                v.environment,
-                v.name,
-                value,
-                v.is_json,
-                v.is_sensitive,
-                v.visible_build,
-                v.visible_runtime,
-                v.is_enabled,
-                v.is_inheritable
+                "platform variable:create --no-wait --yes --level=environment --project={} --environment={} --name={} --value={} --json={} --sensitive={} --visible-build={} --visible-runtime={} --enabled={} --inheritable={}",
+                destination,
+                environment,
+                quote(&r.name),
+                quote(&r.value),
+                r.is_json,
+                r.is_sensitive,
+                r.visible_build,
+                r.visible_runtime,
+                r.is_enabled.unwrap_or(true),
+                r.is_inheritable.unwrap_or(true)
             );
         }
         println!(
             "platform redeploy --project={} --environment={}",
-            args.destination, environment
+            destination, environment
         );
     }
+}
+
+/// Create or update every variable on the destination, printing the outcome
+/// of each, then optionally redeploy
+async fn apply(
+    client: &platform::ApiClient,
+    destination: &str,
+    environments: &[String],
+    redeploy: bool,
+    variables: &[SourceVariable],
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let existing = apply::Existing::list(client, destination, variables).await?;
+    let mut failed = false;
+    for v in variables.iter() {
+        let outcome = apply::apply(client, destination, &existing, v).await;
+        failed |= outcome.is_failure();
+        println!("{} {} {}", v.level, v.request.name, outcome);
+    }
+
+    if redeploy {
+        for environment in environments.iter() {
+            match client.redeploy_environment(destination, environment).await {
+                Ok(activity) => println!("redeploy {} {}", environment, activity.id),
+                Err(error) => {
+                    failed = true;
+                    println!("redeploy {} failed: {}", environment, error);
+                }
+            }
+        }
+    }
 
-    Ok(())
+    Ok(exit_code(failed))
 }
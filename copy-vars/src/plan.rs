@@ -0,0 +1,231 @@
+use platform::{ApiClient, VariableRequest};
+use std::fmt;
+
+use crate::source::{Level, SourceVariable};
+
+const MASK: &str = "********";
+
+/// A variable as it currently exists on the destination
+struct Existing {
+    name: String,
+    value: Option<String>,
+    is_sensitive: bool,
+    flags: Vec<(&'static str, bool)>,
+}
+
+/// How a flag or the value differs between source and destination
+#[derive(Debug, PartialEq, Eq)]
+pub struct Difference {
+    pub field: &'static str,
+    pub source: String,
+    pub destination: String,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.destination, self.source)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    /// Only on the source, would be created
+    Add { value: String },
+    /// On both, with the same value and flags
+    Unchanged,
+    /// On both, differing
+    Modify(Vec<Difference>),
+    /// Sensitive on either side, so the values can't be compared - only the
+    /// flags are
+    Sensitive(Vec<Difference>),
+    /// Only on the destination, would be left alone
+    DestinationOnly,
+}
+
+/// One line of the plan
+#[derive(Debug)]
+pub struct Entry {
+    pub level: Level,
+    pub name: String,
+    pub change: Change,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |differences: &[Difference]| {
+            differences
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match &self.change {
+            Change::Add { value } => write!(f, "+ {} {} = {}", self.level, self.name, value),
+            Change::Unchanged => write!(f, "= {} {}", self.level, self.name),
+            Change::Modify(differences) => {
+                write!(f, "~ {} {} {}", self.level, self.name, join(differences))
+            }
+            Change::Sensitive(differences) if differences.is_empty() => write!(
+                f,
+                "? {} {} (sensitive, value not compared)",
+                self.level, self.name
+            ),
+            Change::Sensitive(differences) => write!(
+                f,
+                "? {} {} {} (sensitive, value not compared)",
+                self.level,
+                self.name,
+                join(differences)
+            ),
+            Change::DestinationOnly => {
+                write!(f, "- {} {} (only on destination)", self.level, self.name)
+            }
+        }
+    }
+}
+
+fn flags(request: &VariableRequest) -> Vec<(&'static str, bool)> {
+    let mut flags = vec![
+        ("is_json", request.is_json),
+        ("is_sensitive", request.is_sensitive),
+        ("visible_build", request.visible_build),
+        ("visible_runtime", request.visible_runtime),
+    ];
+    if let Some(is_enabled) = request.is_enabled {
+        flags.push(("is_enabled", is_enabled));
+    }
+    if let Some(is_inheritable) = request.is_inheritable {
+        flags.push(("is_inheritable", is_inheritable));
+    }
+    flags
+}
+
+fn compare(source: &SourceVariable, existing: Option<&Existing>) -> Change {
+    let request = &source.request;
+    let Some(existing) = existing else {
+        let value = if request.is_sensitive || source.value_unknown {
+            MASK.to_string()
+        } else {
+            format!("'{}'", request.value)
+        };
+        return Change::Add { value };
+    };
+
+    let mut differences: Vec<Difference> = flags(request)
+        .into_iter()
+        .zip(existing.flags.iter())
+        .filter(|((_, source), (_, destination))| source != destination)
+        .map(|((field, source), (_, destination))| Difference {
+            field,
+            source: source.to_string(),
+            destination: destination.to_string(),
+        })
+        .collect();
+
+    if request.is_sensitive || existing.is_sensitive || source.value_unknown {
+        return Change::Sensitive(differences);
+    }
+
+    if existing.value.as_deref() != Some(request.value.as_str()) {
+        differences.insert(
+            0,
+            Difference {
+                field: "value",
+                source: format!("'{}'", request.value),
+                destination: existing
+                    .value
+                    .as_ref()
+                    .map(|v| format!("'{}'", v))
+                    .unwrap_or_else(|| MASK.to_string()),
+            },
+        );
+    }
+
+    if differences.is_empty() {
+        Change::Unchanged
+    } else {
+        Change::Modify(differences)
+    }
+}
+
+/// Compare the variables of the source with the ones `destination` has on
+/// the same levels. Inherited variables are left out on both sides.
+pub async fn plan(
+    client: &ApiClient,
+    destination: &str,
+    environments: &[String],
+    variables: &[SourceVariable],
+) -> Result<Vec<Entry>, platform::Error> {
+    let mut levels = vec![(
+        Level::Project,
+        client
+            .project_variables(destination)
+            .await?
+            .into_iter()
+            .map(|v| Existing {
+                flags: flags(&VariableRequest {
+                    is_json: v.is_json,
+                    is_sensitive: v.is_sensitive,
+                    visible_build: v.visible_build,
+                    visible_runtime: v.visible_runtime,
+                    ..VariableRequest::new(&v.name, "")
+                }),
+                name: v.name,
+                value: v.value,
+                is_sensitive: v.is_sensitive,
+            })
+            .collect::<Vec<_>>(),
+    )];
+    for environment in environments {
+        levels.push((
+            Level::Environment(environment.clone()),
+            client
+                .environment_variables(destination, environment)
+                .await?
+                .into_iter()
+                .filter(|v| !v.inherited)
+                .map(|v| Existing {
+                    flags: flags(&VariableRequest {
+                        is_json: v.is_json,
+                        is_sensitive: v.is_sensitive,
+                        visible_build: v.visible_build,
+                        visible_runtime: v.visible_runtime,
+                        is_enabled: Some(v.is_enabled),
+                        is_inheritable: Some(v.is_inheritable),
+                        ..VariableRequest::new(&v.name, "")
+                    }),
+                    name: v.name,
+                    value: v.value,
+                    is_sensitive: v.is_sensitive,
+                })
+                .collect(),
+        ));
+    }
+
+    let mut entries = Vec::new();
+    for (level, existing) in levels.iter() {
+        let sources: Vec<&SourceVariable> = variables
+            .iter()
+            .filter(|v| &v.level == level && !v.inherited)
+            .collect();
+        for source in sources.iter() {
+            let destination = existing.iter().find(|e| e.name == source.request.name);
+            entries.push(Entry {
+                level: level.clone(),
+                name: source.request.name.clone(),
+                change: compare(source, destination),
+            });
+        }
+        for e in existing.iter() {
+            if !sources.iter().any(|s| s.request.name == e.name) {
+                entries.push(Entry {
+                    level: level.clone(),
+                    name: e.name.clone(),
+                    change: Change::DestinationOnly,
+                });
+            }
+        }
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+use crate::source::SourceVariable;
+
+const VERSION: u32 = 1;
+
+/// Machine-readable form of what the script output would do, written with
+/// `--format json` or `copy-vars export` and applied with `copy-vars import`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlanFile {
+    pub version: u32,
+    pub source: String,
+    /// Not known for exports, given on import instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    pub environments: Vec<String>,
+    pub variables: Vec<SourceVariable>,
+}
+
+impl PlanFile {
+    pub fn new(
+        source: &str,
+        destination: Option<&str>,
+        environments: &[String],
+        variables: Vec<SourceVariable>,
+    ) -> PlanFile {
+        PlanFile {
+            version: VERSION,
+            source: source.to_string(),
+            destination: destination.map(String::from),
+            environments: environments.to_vec(),
+            variables,
+        }
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>, serde_json::Error> {
+        serde_json::to_vec_pretty(self)
+    }
+
+    pub fn from_slice(data: &[u8]) -> Result<PlanFile, Box<dyn std::error::Error>> {
+        let plan: PlanFile = serde_json::from_slice(data)?;
+        if plan.version != VERSION {
+            return Err(format!("unsupported plan version {}", plan.version).into());
+        }
+        Ok(plan)
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// What [`RuntimeReader::read`] returns, boxed so readers can be `dyn`
+pub type ReadFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<HashMap<String, String>, Box<dyn std::error::Error>>> + 'a>>;
+
+/// Reads the environment variables of a running app, for values the API
+/// doesn't return
+pub trait RuntimeReader {
+    /// Every environment variable of `environment` in one go
+    fn read<'a>(&'a self, project: &'a str, environment: &'a str) -> ReadFuture<'a>;
+}
+
+/// `platform ssh ... env -0` through the Platform.sh CLI
+pub struct PlatformCli {
+    pub app: Option<String>,
+}
+
+impl RuntimeReader for PlatformCli {
+    fn read<'a>(&'a self, project: &'a str, environment: &'a str) -> ReadFuture<'a> {
+        Box::pin(async move {
+            let mut params = vec!["ssh", "-p", project, "-e", environment];
+            if let Some(app) = &self.app {
+                params.push("-A");
+                params.push(app);
+            }
+            params.push("env -0");
+
+            let output = Command::new("platform")
+                .args(params)
+                .stdin(Stdio::null())
+                .output()
+                .await
+                .map_err(|error| format!("running platform ssh: {}", error))?;
+            if !output.status.success() {
+                return Err(format!(
+                    "platform ssh failed ({}): {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )
+                .into());
+            }
+
+            Ok(parse_env(&output.stdout))
+        })
+    }
+}
+
+/// Parse the NUL separated `NAME=value` pairs `env -0` prints
+fn parse_env(output: &[u8]) -> HashMap<String, String> {
+    output
+        .split(|b| *b == 0)
+        .filter_map(|entry| {
+            let entry = String::from_utf8_lossy(entry);
+            let (name, value) = entry.split_once('=')?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,6 @@
+/// Quote `value` as a single POSIX shell word. Everything between single
+/// quotes is literal - newlines and `$` included - so only the single quote
+/// itself needs closing, escaping and reopening.
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
@@ -0,0 +1,159 @@
+use platform::{ApiClient, VariableRequest};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::runtime::RuntimeReader;
+
+/// Where a variable is defined
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Project,
+    Environment(String),
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Project => write!(f, "project"),
+            Level::Environment(environment) => write!(f, "environment:{}", environment),
+        }
+    }
+}
+
+/// A variable of the source project, with its value looked up if the API
+/// doesn't return it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceVariable {
+    pub level: Level,
+    #[serde(flatten)]
+    pub request: VariableRequest,
+    /// Sensitive and not readable at runtime - `request.value` is empty
+    pub value_unknown: bool,
+    /// Why reading the value at runtime failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime_error: Option<String>,
+    /// Inherited from the project or a parent environment, not set here
+    pub inherited: bool,
+}
+
+/// Which values to look up at runtime: (index into the collected variables,
+/// name without the `env:` prefix), per environment
+type Pending = Vec<(String, Vec<(usize, String)>)>;
+
+fn runtime_name(name: &str) -> Option<String> {
+    name.strip_prefix("env:").map(String::from)
+}
+
+fn pend(pending: &mut Pending, environment: &str, index: usize, name: String) {
+    match pending.iter_mut().find(|(e, _)| e == environment) {
+        Some((_, names)) => names.push((index, name)),
+        None => pending.push((environment.to_string(), vec![(index, name)])),
+    }
+}
+
+/// Every variable of `project` and of each of `environments`, project level
+/// first. Values the API doesn't return are read at runtime through `reader`,
+/// once per environment (for project variables: the first environment), or
+/// left unknown without one. Values that can't be read are reported on the
+/// variable.
+pub async fn collect(
+    client: &ApiClient,
+    reader: Option<&dyn RuntimeReader>,
+    project: &str,
+    environments: &[String],
+) -> Result<Vec<SourceVariable>, Box<dyn std::error::Error>> {
+    let mut collected = Vec::new();
+    let mut pending = Pending::new();
+
+    for v in client.project_variables(project).await? {
+        let (value, value_unknown) = match v.value {
+            Some(value) => (value, false),
+            None => {
+                if let Some(name) = runtime_name(&v.name).filter(|_| v.visible_runtime) {
+                    pend(&mut pending, &environments[0], collected.len(), name);
+                }
+                ("".to_string(), true)
+            }
+        };
+
+        collected.push(SourceVariable {
+            level: Level::Project,
+            request: VariableRequest {
+                name: v.name,
+                value,
+                is_json: v.is_json,
+                is_sensitive: v.is_sensitive,
+                visible_build: v.visible_build,
+                visible_runtime: v.visible_runtime,
+                is_enabled: None,
+                is_inheritable: None,
+            },
+            value_unknown,
+            runtime_error: None,
+            inherited: false,
+        });
+    }
+
+    for environment in environments {
+        for v in client.environment_variables(project, environment).await? {
+            let (value, value_unknown) = match v.value {
+                _ if v.inherited => ("".to_string(), false),
+                Some(value) => (value, false),
+                None => {
+                    if let Some(name) = runtime_name(&v.name).filter(|_| v.visible_runtime) {
+                        pend(&mut pending, &v.environment, collected.len(), name);
+                    }
+                    ("".to_string(), true)
+                }
+            };
+
+            collected.push(SourceVariable {
+                level: Level::Environment(environment.clone()),
+                request: VariableRequest {
+                    name: v.name,
+                    value,
+                    is_json: v.is_json,
+                    is_sensitive: v.is_sensitive,
+                    visible_build: v.visible_build,
+                    visible_runtime: v.visible_runtime,
+                    is_enabled: Some(v.is_enabled),
+                    is_inheritable: Some(v.is_inheritable),
+                },
+                value_unknown,
+                runtime_error: None,
+                inherited: v.inherited,
+            });
+        }
+    }
+
+    let Some(reader) = reader else {
+        return Ok(collected);
+    };
+    for (environment, names) in pending {
+        match reader.read(project, &environment).await {
+            Ok(runtime) => {
+                for (index, name) in names {
+                    let v = &mut collected[index];
+                    match runtime.get(&name) {
+                        Some(value) => {
+                            v.request.value = value.clone();
+                            v.value_unknown = false;
+                        }
+                        None => {
+                            v.runtime_error =
+                                Some(format!("${} is not set on {}", name, environment))
+                        }
+                    }
+                }
+            }
+            Err(error) => {
+                for (index, _) in names {
+                    collected[index].runtime_error = Some(error.to_string());
+                }
+            }
+        }
+    }
+
+    Ok(collected)
+}
@@ -0,0 +1,629 @@
+use platform_mock::{MockServer, Seed};
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+const SEED: &str = r#"
+projects:
+  source1234567:
+    environments:
+      - { name: main, is_main: true }
+      - { name: staging, parent: main }
+    variables:
+      - { name: env:APP_ENV, value: production }
+      - { name: settings, value: '{"debug": false}', is_json: true, visible_runtime: false }
+      - { name: env:SMTP_PASSWORD, is_sensitive: true, visible_runtime: false }
+    environment_variables:
+      main:
+        - { name: env:APP_ENV, value: production, inherited: true }
+        - { name: env:MAIN_ONLY, value: "yes", is_inheritable: false }
+      staging:
+        - { name: env:STAGING_ONLY, value: "1", visible_build: false }
+  dest7654321:
+    environments:
+      - { name: main, is_main: true }
+      - { name: staging, parent: main }
+    variables:
+      - { name: env:APP_ENV, value: development }
+  plan7654321:
+    environments:
+      - { name: main, is_main: true }
+      - { name: staging, parent: main }
+    variables:
+      - { name: env:APP_ENV, value: development }
+      - { name: settings, value: '{"debug": false}', is_json: true, visible_runtime: false }
+      - { name: env:SMTP_PASSWORD, is_sensitive: true }
+      - { name: env:LEGACY, value: "1" }
+    environment_variables:
+      main:
+        - { name: env:APP_ENV, value: development, inherited: true }
+        - { name: env:MAIN_ONLY, value: "yes" }
+  quote1234567:
+    environments:
+      - { name: main, is_main: true }
+    variables:
+      - name: env:COMPOSER_AUTH
+        value: |-
+          {"http-basic": {"repo.example.com": {"username": "o'brien", "password": "$ecret`id`"}}}
+          second line \ "quoted"
+  runtime1234567:
+    environments:
+      - { name: main, is_main: true }
+      - { name: staging, parent: main }
+    variables:
+      - { name: env:DB_PASSWORD, is_sensitive: true }
+      - { name: env:API_KEY, is_sensitive: true }
+    environment_variables:
+      main:
+        - { name: env:MAIN_SECRET, is_sensitive: true }
+      staging:
+        - { name: env:STAGING_SECRET, is_sensitive: true }
+faults:
+  - { path: /projects/source1234567/variables, status: 401, times: 1 }
+"#;
+
+async fn copy_vars(server: &MockServer, args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_copy-vars"))
+        .args(args)
+        .env("PLATFORMSH_API_URL", server.url())
+        .env("PLATFORMSH_AUTH_URL", server.url())
+        .env("PLATFORMSH_CLI_TOKEN", "api-token")
+        .output()
+        .await
+        .unwrap()
+}
+
+/// An empty directory of its own for `test`
+fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("copy-vars-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// `PATH` with a scratch directory first, holding a `platform` that runs
+/// `script`
+fn fake_platform(test: &str, script: &str) -> String {
+    let dir = scratch_dir(test);
+    let platform = dir.join("platform");
+    std::fs::write(&platform, format!("#!/bin/sh\n{}\n", script)).unwrap();
+    std::fs::set_permissions(
+        &platform,
+        std::os::unix::fs::PermissionsExt::from_mode(0o755),
+    )
+    .unwrap();
+    format!("{}:{}", dir.display(), std::env::var("PATH").unwrap())
+}
+
+#[tokio::test]
+async fn prints_commands_for_every_variable() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+
+    let output = copy_vars(
+        &server,
+        &[
+            "-p",
+            "source1234567",
+            "-d",
+            "dest",
+            "-e",
+            "main",
+            "-e",
+            "staging",
+        ],
+    )
+    .await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "platform variable:create --no-wait --yes --level=project --project=dest --name='env:APP_ENV' --value='production' --json=false --sensitive=false --visible-build=true --visible-runtime=true",
+            "platform variable:create --no-wait --yes --level=project --project=dest --name='settings' --value='{\"debug\": false}' --json=true --sensitive=false --visible-build=true --visible-runtime=false",
+            "# env:SMTP_PASSWORD must be found seperately",
+            "# ",
+            "platform variable:create --no-wait --yes --level=project --project=dest --name='env:SMTP_PASSWORD' --value='' --json=false --sensitive=true --visible-build=true --visible-runtime=false",
+            "# env:APP_ENV inherited",
+            "platform variable:create --no-wait --yes --level=environment --project=dest --environment=main --name='env:MAIN_ONLY' --value='yes' --json=false --sensitive=false --visible-build=true --visible-runtime=true --enabled=true --inheritable=false",
+            "platform redeploy --project=dest --environment=main",
+            "platform variable:create --no-wait --yes --level=environment --project=dest --environment=staging --name='env:STAGING_ONLY' --value='1' --json=false --sensitive=false --visible-build=false --visible-runtime=true --enabled=true --inheritable=true",
+            "platform redeploy --project=dest --environment=staging",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn fails_on_unknown_environment() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+
+    let output = copy_vars(
+        &server,
+        &["-p", "source1234567", "-d", "dest", "-e", "nope"],
+    )
+    .await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("404"));
+}
+
+#[tokio::test]
+async fn applies_variables_through_the_api() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+
+    let output = copy_vars(
+        &server,
+        &[
+            "-p",
+            "source1234567",
+            "-d",
+            "dest7654321",
+            "-e",
+            "main",
+            "-e",
+            "staging",
+            "--apply",
+            "--redeploy",
+        ],
+    )
+    .await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 8, "{}", stdout);
+    assert_eq!(
+        lines[..6],
+        [
+            "project env:APP_ENV updated",
+            "project settings created",
+            "project env:SMTP_PASSWORD skipped (sensitive value must be set separately)",
+            "environment:main env:APP_ENV skipped (inherited)",
+            "environment:main env:MAIN_ONLY created",
+            "environment:staging env:STAGING_ONLY created",
+        ]
+    );
+    assert!(lines[6].starts_with("redeploy main "));
+    assert!(lines[7].starts_with("redeploy staging "));
+
+    let requests = server.requests();
+    assert!(requests.contains(&"PATCH /projects/dest7654321/variables/env%3AAPP_ENV".to_string()));
+    assert!(requests.contains(&"POST /projects/dest7654321/variables".to_string()));
+    assert!(
+        requests.contains(&"POST /projects/dest7654321/environments/staging/variables".to_string())
+    );
+    assert!(
+        requests.contains(&"POST /projects/dest7654321/environments/main/redeploy".to_string())
+    );
+    // Existing variables are listed once per level, not once per variable
+    let listed = |path: &str| {
+        requests
+            .iter()
+            .filter(|r| r.split('?').next() == Some(&format!("GET {}", path)))
+            .count()
+    };
+    assert_eq!(listed("/projects/dest7654321/variables"), 1);
+    assert_eq!(
+        listed("/projects/dest7654321/environments/main/variables"),
+        1
+    );
+    assert_eq!(
+        listed("/projects/dest7654321/environments/staging/variables"),
+        1
+    );
+}
+
+#[tokio::test]
+async fn redeploy_requires_apply() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+
+    let output = copy_vars(
+        &server,
+        &["-p", "source1234567", "-d", "dest7654321", "--redeploy"],
+    )
+    .await;
+    assert!(!output.status.success());
+    assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn plans_differences_with_the_destination() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+
+    let output = copy_vars(
+        &server,
+        &[
+            "-p",
+            "source1234567",
+            "-d",
+            "plan7654321",
+            "-e",
+            "main",
+            "-e",
+            "staging",
+            "--plan",
+        ],
+    )
+    .await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "~ project env:APP_ENV value: 'development' -> 'production'",
+            "= project settings",
+            "? project env:SMTP_PASSWORD visible_runtime: true -> false (sensitive, value not compared)",
+            "- project env:LEGACY (only on destination)",
+            "~ environment:main env:MAIN_ONLY is_inheritable: true -> false",
+            "+ environment:staging env:STAGING_ONLY = '1'",
+        ]
+    );
+    assert!(
+        !server
+            .requests()
+            .iter()
+            .any(|r| !r.starts_with("GET ") && !r.starts_with("POST /oauth2"))
+    );
+}
+
+#[tokio::test]
+async fn quotes_values_for_the_shell() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+    let value = "{\"http-basic\": {\"repo.example.com\": {\"username\": \"o'brien\", \"password\": \"$ecret`id`\"}}}\nsecond line \\ \"quoted\"";
+
+    let output = copy_vars(&server, &["-p", "quote1234567", "-d", "dest"]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success());
+
+    // Run the generated script against a `platform` that prints its arguments
+    let path = fake_platform("quote", "printf '%s\\0' \"$@\"");
+    let ran = Command::new("sh")
+        .arg("-c")
+        .arg(&stdout)
+        .env("PATH", path)
+        .output()
+        .await
+        .unwrap();
+    assert!(ran.status.success());
+
+    let args: Vec<String> = String::from_utf8(ran.stdout)
+        .unwrap()
+        .split('\0')
+        .map(String::from)
+        .collect();
+    assert!(args.contains(&"--name=env:COMPOSER_AUTH".to_string()));
+    assert!(args.contains(&format!("--value={}", value)), "{:?}", args);
+}
+
+#[tokio::test]
+async fn imports_a_json_plan() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+
+    let output = copy_vars(
+        &server,
+        &[
+            "-p",
+            "source1234567",
+            "-d",
+            "dest",
+            "-e",
+            "main",
+            "-e",
+            "staging",
+            "--format",
+            "json",
+        ],
+    )
+    .await;
+    assert!(output.status.success());
+    let plan: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(plan["destination"], "dest");
+    assert_eq!(plan["variables"][0]["level"], "project");
+    assert_eq!(plan["variables"][0]["name"], "env:APP_ENV");
+    assert_eq!(plan["variables"][4]["level"]["environment"], "main");
+
+    let file = scratch_dir("import").join("plan.json");
+    std::fs::write(&file, &output.stdout).unwrap();
+
+    let output = copy_vars(
+        &server,
+        &["import", file.to_str().unwrap(), "-p", "dest7654321"],
+    )
+    .await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        stdout.lines().collect::<Vec<_>>(),
+        [
+            "project env:APP_ENV updated",
+            "project settings created",
+            "project env:SMTP_PASSWORD skipped (sensitive value must be set separately)",
+            "environment:main env:APP_ENV skipped (inherited)",
+            "environment:main env:MAIN_ONLY created",
+            "environment:staging env:STAGING_ONLY created",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn exports_and_imports_with_a_passphrase() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+    let file = scratch_dir("passphrase").join("vars.age");
+    let out = file.to_str().unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_copy-vars"))
+        .args(["export", "-p", "source1234567", "-e", "main", "--out", out])
+        .env("PLATFORMSH_API_URL", server.url())
+        .env("PLATFORMSH_AUTH_URL", server.url())
+        .env("PLATFORMSH_CLI_TOKEN", "api-token")
+        .env("COPY_VARS_PASSPHRASE", "correct horse battery staple")
+        .output()
+        .await
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let encrypted = std::fs::read(&file).unwrap();
+    assert!(encrypted.starts_with(b"age-encryption.org/v1"));
+    assert!(!String::from_utf8_lossy(&encrypted).contains("production"));
+
+    // Wrong passphrase
+    let output = Command::new(env!("CARGO_BIN_EXE_copy-vars"))
+        .args(["import", "-p", "dest7654321", out])
+        .env("PLATFORMSH_API_URL", server.url())
+        .env("PLATFORMSH_AUTH_URL", server.url())
+        .env("PLATFORMSH_CLI_TOKEN", "api-token")
+        .env("COPY_VARS_PASSPHRASE", "wrong")
+        .output()
+        .await
+        .unwrap();
+    assert!(!output.status.success());
+
+    // Read from stdin
+    let mut child = Command::new(env!("CARGO_BIN_EXE_copy-vars"))
+        .args(["import", "-p", "dest7654321", "--passphrase", out])
+        .env("PLATFORMSH_API_URL", server.url())
+        .env("PLATFORMSH_AUTH_URL", server.url())
+        .env("PLATFORMSH_CLI_TOKEN", "api-token")
+        .env_remove("COPY_VARS_PASSPHRASE")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin
+        .write_all(b"correct horse battery staple\n")
+        .await
+        .unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().await.unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        stdout.lines().collect::<Vec<_>>(),
+        [
+            "project env:APP_ENV updated",
+            "project settings created",
+            "project env:SMTP_PASSWORD skipped (sensitive value must be set separately)",
+            "environment:main env:APP_ENV skipped (inherited)",
+            "environment:main env:MAIN_ONLY created",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn exports_and_imports_with_a_key_pair() {
+    use age::secrecy::ExposeSecret;
+
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+    let dir = scratch_dir("key-pair");
+    let identity = age::x25519::Identity::generate();
+    let identity_file = dir.join("key.txt");
+    std::fs::write(
+        &identity_file,
+        format!("{}\n", identity.to_string().expose_secret()),
+    )
+    .unwrap();
+    let file = dir.join("vars.age");
+
+    let output = copy_vars(
+        &server,
+        &[
+            "export",
+            "-p",
+            "source1234567",
+            "-e",
+            "staging",
+            "--out",
+            file.to_str().unwrap(),
+            "--recipient",
+            &identity.to_public().to_string(),
+        ],
+    )
+    .await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Without the plan's destination
+    let output = copy_vars(&server, &["import", file.to_str().unwrap()]).await;
+    assert!(!output.status.success());
+
+    let output = copy_vars(
+        &server,
+        &[
+            "import",
+            file.to_str().unwrap(),
+            "-p",
+            "dest7654321",
+            "--identity",
+            identity_file.to_str().unwrap(),
+        ],
+    )
+    .await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("environment:staging env:STAGING_ONLY created"));
+}
+
+#[tokio::test]
+async fn export_needs_a_key() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+    let file = scratch_dir("no-key").join("vars.age");
+
+    let output = copy_vars(
+        &server,
+        &[
+            "export",
+            "-p",
+            "source1234567",
+            "--out",
+            file.to_str().unwrap(),
+        ],
+    )
+    .await;
+    assert!(!output.status.success());
+    assert!(!file.exists());
+
+    // The passphrase itself is never taken from the command line
+    let output = copy_vars(
+        &server,
+        &[
+            "export",
+            "-p",
+            "source1234567",
+            "--out",
+            file.to_str().unwrap(),
+            "--passphrase",
+            "hunter2",
+        ],
+    )
+    .await;
+    assert!(!output.status.success());
+    assert!(!file.exists());
+}
+
+#[tokio::test]
+async fn reads_runtime_values_once_per_environment() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+    let path = fake_platform(
+        "runtime",
+        r#"echo "$@" >> "$(dirname "$0")/calls"
+case "$*" in
+  *"-e main "*) printf 'PATH=/usr/bin\0DB_PASSWORD=s3=cr\nt\0MAIN_SECRET=m41n\0' ;;
+  *) echo "no ssh access" >&2; exit 1 ;;
+esac"#,
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_copy-vars"))
+        .args([
+            "-p",
+            "runtime1234567",
+            "-d",
+            "dest",
+            "-e",
+            "main",
+            "-e",
+            "staging",
+            "-A",
+            "app",
+        ])
+        .env("PLATFORMSH_API_URL", server.url())
+        .env("PLATFORMSH_AUTH_URL", server.url())
+        .env("PLATFORMSH_CLI_TOKEN", "api-token")
+        .env("PATH", &path)
+        .output()
+        .await
+        .unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    let calls = std::fs::read_to_string(
+        std::path::Path::new(path.split(':').next().unwrap()).join("calls"),
+    )
+    .unwrap();
+    assert_eq!(
+        calls.lines().collect::<Vec<_>>(),
+        [
+            "ssh -p runtime1234567 -e main -A app env -0",
+            "ssh -p runtime1234567 -e staging -A app env -0",
+        ]
+    );
+
+    assert!(stdout.contains("--name='env:DB_PASSWORD' --value='s3=cr\nt'"));
+    assert!(stdout.contains("--name='env:MAIN_SECRET' --value='m41n'"));
+    assert!(stdout.contains("# env:API_KEY could not be read: $API_KEY is not set on main"));
+    assert!(!stdout.contains("--name='env:API_KEY'"));
+    assert!(stdout.contains("# env:STAGING_SECRET could not be read: platform ssh failed"));
+    assert!(!stdout.contains("--name='env:STAGING_SECRET'"));
+    assert!(stderr.contains("project env:API_KEY: $API_KEY is not set on main"));
+    assert!(stderr.contains("environment:staging env:STAGING_SECRET: platform ssh failed"));
+    assert!(stderr.contains("no ssh access"));
+}
+
+#[tokio::test]
+async fn plans_without_reading_runtime_values() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+    let path = fake_platform("plan-runtime", r#"echo "$@" >> "$(dirname "$0")/calls""#);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_copy-vars"))
+        .args([
+            "-p",
+            "runtime1234567",
+            "-d",
+            "dest7654321",
+            "-e",
+            "main",
+            "--plan",
+        ])
+        .env("PLATFORMSH_API_URL", server.url())
+        .env("PLATFORMSH_AUTH_URL", server.url())
+        .env("PLATFORMSH_CLI_TOKEN", "api-token")
+        .env("PATH", &path)
+        .output()
+        .await
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("project env:DB_PASSWORD"), "{}", stdout);
+
+    let calls = std::path::Path::new(path.split(':').next().unwrap()).join("calls");
+    assert!(!calls.exists(), "platform was run");
+}
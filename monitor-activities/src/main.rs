@@ -0,0 +1,111 @@
+use chrono::{DateTime, Local};
+use clap::Parser;
+use std::time::Duration;
+use tracing::{error, warn};
+
+mod monitor;
+
+/// Watch the activities of every project for failed or stuck deploys
+#[derive(Parser, Debug)]
+struct Args {
+    /// Platform Access Token
+    #[arg(long, env = "PLATFORMSH_CLI_TOKEN")]
+    token: String,
+
+    /// API base url
+    #[arg(
+        long,
+        env = "PLATFORMSH_API_URL",
+        default_value = "https://api.platform.sh"
+    )]
+    api_url: String,
+
+    /// OAuth2 base url
+    #[arg(
+        long,
+        env = "PLATFORMSH_AUTH_URL",
+        default_value = "https://auth.api.platform.sh"
+    )]
+    auth_url: String,
+
+    /// Seconds between polls
+    #[arg(long, default_value_t = 60)]
+    interval: u64,
+
+    /// Report activities still running this many minutes after they started
+    #[arg(long, default_value_t = 30)]
+    stuck_after: i64,
+
+    /// Report activities created since then (RFC 3339) [default: now]
+    #[arg(long)]
+    since: Option<DateTime<Local>>,
+
+    /// POST every event as JSON to this url, instead of printing JSON lines
+    #[arg(long, env = "MONITOR_WEBHOOK_URL")]
+    webhook: Option<String>,
+
+    /// Stop after this many polls, instead of running forever
+    #[arg(long)]
+    polls: Option<u64>,
+
+    /// Number of projects to check in parallel
+    #[arg(long, default_value_t = 4)]
+    concurrency: usize,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+
+    let client = platform::ApiClient::builder()
+        .api_url(&args.api_url)
+        .auth_url(&args.auth_url)
+        .build(&args.token)
+        .await?;
+    let webhook = reqwest::Client::new();
+
+    let mut monitor = monitor::Monitor::new(
+        client,
+        chrono::Duration::minutes(args.stuck_after),
+        args.since.unwrap_or_else(Local::now),
+        args.concurrency.max(1),
+    );
+
+    let mut polls = 0;
+    loop {
+        match monitor.poll().await {
+            Ok(events) => {
+                for event in events {
+                    match &args.webhook {
+                        Some(url) => {
+                            let result = webhook
+                                .post(url)
+                                .json(&event)
+                                .send()
+                                .await
+                                .and_then(|response| response.error_for_status());
+                            if let Err(error) = result {
+                                error!(%error, activity = event.activity, "webhook failed");
+                            }
+                        }
+                        None => println!("{}", serde_json::to_string(&event)?),
+                    }
+                }
+            }
+            // Keep going, the API may well be back by the next poll
+            Err(error) => warn!(%error, "poll failed"),
+        }
+
+        polls += 1;
+        if args.polls.is_some_and(|max| polls >= max) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_secs(args.interval)).await;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,275 @@
+use chrono::{DateTime, Duration, Local};
+use futures::{StreamExt, TryStreamExt};
+use serde::Serialize;
+use std::collections::{hash_map::Entry, HashMap};
+use tracing::{info, warn};
+
+/// Why an activity is reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// Completed with result "failure"
+    Failed,
+    /// Started longer ago than the threshold and still not finished
+    Stuck,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub event: EventKind,
+    pub project: String,
+    pub project_title: String,
+    pub environments: Vec<String>,
+    pub activity: String,
+    pub r#type: String,
+    pub state: String,
+    pub result: Option<String>,
+    pub description: Option<String>,
+    pub created_at: Option<DateTime<Local>>,
+    pub started_at: Option<DateTime<Local>>,
+    pub completed_at: Option<DateTime<Local>>,
+}
+
+impl Event {
+    fn new(
+        kind: EventKind,
+        subscription: &platform::Subscription,
+        activity: &platform::Activity,
+    ) -> Event {
+        Event {
+            event: kind,
+            project: subscription.project_id.clone(),
+            project_title: subscription.project_title.clone(),
+            environments: activity.environments.clone(),
+            activity: activity.id.clone(),
+            r#type: activity.r#type.clone(),
+            state: activity.state.clone(),
+            result: activity.result.clone(),
+            description: activity.text.clone(),
+            created_at: activity.created_at,
+            started_at: activity.started_at,
+            completed_at: activity.completed_at,
+        }
+    }
+}
+
+/// How far a project has been checked
+#[derive(Debug)]
+struct Cursor {
+    /// Activities created before this have all finished and been checked
+    since: DateTime<Local>,
+    /// Activities created before the monitor's start that were still running
+    /// then, by when they started, followed until they finish
+    running: HashMap<String, DateTime<Local>>,
+    /// Events already reported for activities created since then
+    reported: HashMap<(String, EventKind), DateTime<Local>>,
+}
+
+pub struct Monitor {
+    client: platform::ApiClient,
+    stuck_after: Duration,
+    since: DateTime<Local>,
+    concurrency: usize,
+    cursors: HashMap<String, Cursor>,
+}
+
+impl Monitor {
+    /// Report activities created from `since` on
+    pub fn new(
+        client: platform::ApiClient,
+        stuck_after: Duration,
+        since: DateTime<Local>,
+        concurrency: usize,
+    ) -> Monitor {
+        Monitor {
+            client,
+            stuck_after,
+            since,
+            concurrency,
+            cursors: HashMap::new(),
+        }
+    }
+
+    /// Check every subscription once, returning events not reported before.
+    /// Projects that can't be checked are skipped with a warning, so one
+    /// broken project doesn't blind the monitor to the rest.
+    pub async fn poll(&mut self) -> Result<Vec<Event>, platform::Error> {
+        let subscriptions = self.client.subscriptions().await?;
+        info!(subscriptions = subscriptions.len(), "polling");
+
+        let client = &self.client;
+        let cursors = &self.cursors;
+        let since = self.since;
+        let results: Vec<_> = futures::stream::iter(subscriptions.iter())
+            .map(|subscription| async move {
+                let cursor = cursors.get(&subscription.project_id);
+                let filter = platform::ActivityFilter {
+                    after: Some(cursor.map_or(since, |cursor| cursor.since)),
+                    ..Default::default()
+                };
+                let activities = client
+                    .activities_stream(&subscription.project_id, None, &filter)
+                    .try_collect::<Vec<_>>()
+                    .await;
+                let earlier = match cursor {
+                    Some(cursor) if cursor.running.is_empty() => Ok(None),
+                    _ => earlier_activities(client, &subscription.project_id, cursor, since)
+                        .await
+                        .map(Some),
+                };
+                (subscription, activities, earlier)
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        let now = Local::now();
+        let mut events = Vec::new();
+        for (subscription, activities, earlier) in results {
+            let (activities, earlier) = match (activities, earlier) {
+                (Ok(activities), Ok(earlier)) => (activities, earlier),
+                (Err(error), _) | (_, Err(error)) => {
+                    warn!(project = subscription.project_id, %error, "unable to fetch activities");
+                    continue;
+                }
+            };
+            let cursor = self
+                .cursors
+                .entry(subscription.project_id.clone())
+                .or_insert_with(|| Cursor {
+                    since,
+                    running: HashMap::new(),
+                    reported: HashMap::new(),
+                });
+            if let Some(earlier) = earlier {
+                events.extend(cursor.check_earlier(subscription, &earlier, now, self.stuck_after));
+            }
+            events.extend(cursor.check(subscription, &activities, now, self.stuck_after));
+        }
+
+        Ok(events)
+    }
+}
+
+/// Activities created before `since` that are still pending or in progress,
+/// which the filter on creation time leaves out but may well get stuck too,
+/// and the final state of those the cursor followed that no longer are
+async fn earlier_activities(
+    client: &platform::ApiClient,
+    project_id: &str,
+    cursor: Option<&Cursor>,
+    since: DateTime<Local>,
+) -> Result<Vec<platform::Activity>, platform::Error> {
+    let mut activities = Vec::new();
+    for state in ["pending", "in_progress"] {
+        let filter = platform::ActivityFilter {
+            state: Some(state.to_string()),
+            before: Some(since),
+            ..Default::default()
+        };
+        let found: Vec<_> = client
+            .activities_stream(project_id, None, &filter)
+            .try_filter(|a| futures::future::ready(a.created_at.is_some_and(|c| c < since)))
+            .try_collect()
+            .await?;
+        activities.extend(found);
+    }
+
+    let gone: Vec<&String> = cursor
+        .iter()
+        .flat_map(|cursor| cursor.running.keys())
+        .filter(|id| !activities.iter().any(|a| &a.id == *id))
+        .collect();
+    for id in gone {
+        activities.push(client.activity(project_id, id).await?);
+    }
+
+    Ok(activities)
+}
+
+impl Cursor {
+    /// Check activities created before the monitor's start, following those
+    /// still running and forgetting those that have finished
+    fn check_earlier(
+        &mut self,
+        subscription: &platform::Subscription,
+        activities: &[platform::Activity],
+        now: DateTime<Local>,
+        stuck_after: Duration,
+    ) -> Vec<Event> {
+        let mut running = HashMap::new();
+        let mut events = Vec::new();
+        for activity in activities.iter() {
+            let kind = if activity.is_finished() {
+                (activity.result.as_deref() == Some("failure")).then_some(EventKind::Failed)
+            } else {
+                let started_at = self
+                    .running
+                    .get(&activity.id)
+                    .copied()
+                    .or(activity.started_at)
+                    .or(activity.created_at)
+                    .unwrap_or(now);
+                running.insert(activity.id.clone(), started_at);
+                (now - started_at > stuck_after).then_some(EventKind::Stuck)
+            };
+            let Some(kind) = kind else {
+                continue;
+            };
+
+            if let Entry::Vacant(entry) = self.reported.entry((activity.id.clone(), kind)) {
+                entry.insert(activity.created_at.unwrap_or(now));
+                events.push(Event::new(kind, subscription, activity));
+            }
+        }
+        self.running = running;
+
+        events
+    }
+
+    fn check(
+        &mut self,
+        subscription: &platform::Subscription,
+        activities: &[platform::Activity],
+        now: DateTime<Local>,
+        stuck_after: Duration,
+    ) -> Vec<Event> {
+        let mut events = Vec::new();
+        for activity in activities.iter() {
+            let kind = if activity.is_finished() {
+                (activity.result.as_deref() == Some("failure")).then_some(EventKind::Failed)
+            } else {
+                let started_at = activity.started_at.or(activity.created_at);
+                started_at
+                    .is_some_and(|started_at| now - started_at > stuck_after)
+                    .then_some(EventKind::Stuck)
+            };
+            let Some(kind) = kind else {
+                continue;
+            };
+
+            if let Entry::Vacant(entry) = self.reported.entry((activity.id.clone(), kind)) {
+                entry.insert(activity.created_at.unwrap_or(now));
+                events.push(Event::new(kind, subscription, activity));
+            }
+        }
+
+        // Activities still running have to be looked at again, anything older
+        // is done with
+        let newest = activities.iter().filter_map(|a| a.created_at).max();
+        let oldest_running = activities
+            .iter()
+            .filter(|a| !a.is_finished())
+            .filter_map(|a| a.created_at)
+            .min();
+        if let Some(since) = oldest_running.or(newest) {
+            self.since = self.since.max(since);
+        }
+        let since = self.since;
+        let running = &self.running;
+        self.reported
+            .retain(|(id, _), created_at| *created_at >= since || running.contains_key(id));
+
+        events
+    }
+}
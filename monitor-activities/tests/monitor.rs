@@ -0,0 +1,121 @@
+use axum::{extract::State, routing::post, Json, Router};
+use platform_mock::{MockServer, Seed};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use tokio::{net::TcpListener, process::Command};
+
+const SEED: &str = r#"
+organizations:
+  - id: org-adapt
+    name: adapt
+    subscriptions:
+      - { project_id: project1, title: One }
+      - { project_id: project2, title: Two }
+      - { project_id: forbidden, title: Forbidden }
+projects:
+  project1:
+    environments:
+      - { name: main, is_main: true }
+    activities:
+      - { id: old1, type: environment.push, environments: [main], result: failure, created_at: "2023-12-01T10:00:00Z" }
+      - { id: running1, type: environment.sync, environments: [main], state: in_progress, result: null, created_at: "2023-12-15T10:00:00Z" }
+      - { id: queued1, type: environment.backup, environments: [main], state: pending, result: null, created_at: "2023-12-16T10:00:00Z" }
+      # Listed twice on the first poll, then fails
+      - { id: running2, type: environment.sync, environments: [main], result: failure, pending_polls: 2, created_at: "2023-12-17T10:00:00Z" }
+      - { id: push1, type: environment.push, environments: [main], created_at: "2024-05-01T10:00:00Z" }
+      - { id: push2, type: environment.push, environments: [main], result: failure, created_at: "2024-05-02T10:00:00Z" }
+  project2:
+    environments:
+      - { name: main, is_main: true }
+      - { name: staging }
+    activities:
+      - { id: stuck1, type: environment.redeploy, environments: [staging], state: in_progress, result: null, created_at: "2024-05-03T10:00:00Z" }
+      - { id: backup1, type: environment.backup, environments: [main], created_at: "2024-05-04T10:00:00Z" }
+faults:
+  - { path: /projects/forbidden/activities, status: 403 }
+"#;
+
+fn monitor(server: &MockServer) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_monitor-activities"));
+    command
+        .args(["--since", "2024-01-01T00:00:00Z", "--interval", "0"])
+        .env("PLATFORMSH_API_URL", server.url())
+        .env("PLATFORMSH_AUTH_URL", server.url())
+        .env("PLATFORMSH_CLI_TOKEN", "api-token");
+    command
+}
+
+#[tokio::test]
+async fn reports_each_event_once() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+
+    let output = monitor(&server)
+        .args(["--polls", "3"])
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success());
+
+    let mut events: Vec<Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    events.sort_by_key(|event| event["activity"].as_str().unwrap().to_string());
+    let kinds: Vec<(&str, &str)> = events
+        .iter()
+        .map(|event| {
+            (
+                event["activity"].as_str().unwrap(),
+                event["event"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    // Those created before --since are followed if they were still running
+    assert_eq!(
+        kinds,
+        [
+            ("push2", "failed"),
+            ("queued1", "stuck"),
+            ("running1", "stuck"),
+            ("running2", "stuck"),
+            ("running2", "failed"),
+            ("stuck1", "stuck"),
+        ]
+    );
+
+    assert_eq!(events[0]["project_title"], "One");
+    assert_eq!(events[5]["environments"][0], "staging");
+}
+
+#[tokio::test]
+async fn posts_events_to_webhook() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let app = Router::new()
+        .route(
+            "/hook",
+            post(
+                |State(received): State<Arc<Mutex<Vec<Value>>>>, Json(event): Json<Value>| async move {
+                    received.lock().unwrap().push(event);
+                },
+            ),
+        )
+        .with_state(received.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let webhook = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let output = monitor(&server)
+        .args(["--polls", "1", "--webhook", &webhook])
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let received = received.lock().unwrap();
+    // Only one poll, running2 hasn't finished yet
+    assert_eq!(received.len(), 5);
+}
@@ -0,0 +1,173 @@
+//! Activity listing, filtered and paged by creation time like the real API
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Duration};
+use futures::{stream, StreamExt};
+use serde_json::json;
+use std::{collections::HashMap, io, sync::Arc};
+
+use crate::{not_found, MockState, SeedActivity};
+
+type ListParams = Query<Vec<(String, String)>>;
+
+pub(crate) async fn list(
+    State(state): State<Arc<MockState>>,
+    Path(project): Path<String>,
+    Query(params): ListParams,
+) -> Response {
+    list_activities(&state, &project, None, &params)
+}
+
+pub(crate) async fn list_environment(
+    State(state): State<Arc<MockState>>,
+    Path((project, environment)): Path<(String, String)>,
+    Query(params): ListParams,
+) -> Response {
+    list_activities(&state, &project, Some(&environment), &params)
+}
+
+fn list_activities(
+    state: &MockState,
+    project: &str,
+    environment: Option<&str>,
+    params: &[(String, String)],
+) -> Response {
+    let mut seed = state.seed();
+    let Some(project_seed) = seed.projects.get_mut(project) else {
+        return not_found();
+    };
+    if environment.is_some_and(|e| !project_seed.environments.iter().any(|s| s.name == e)) {
+        return not_found();
+    }
+
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let types: Vec<&str> = params
+        .iter()
+        .filter(|(key, _)| key == "type")
+        .map(|(_, value)| value.as_str())
+        .collect();
+    let starts_at = param("starts_at").and_then(|s| DateTime::parse_from_rfc3339(s).ok());
+    let count = param("count")
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(100);
+
+    let current: Vec<SeedActivity> = project_seed
+        .activities
+        .iter()
+        .map(SeedActivity::current)
+        .collect();
+    let mut activities: Vec<&SeedActivity> = current
+        .iter()
+        .filter(|a| environment.is_none_or(|e| a.environments.iter().any(|x| x == e)))
+        .filter(|a| types.is_empty() || types.contains(&a.r#type.as_str()))
+        .filter(|a| param("state").is_none_or(|state| a.state == state))
+        .filter(|a| param("result").is_none_or(|result| a.result.as_deref() == Some(result)))
+        .filter(|a| starts_at.is_none_or(|starts_at| a.created_at <= starts_at))
+        .collect();
+    activities.sort_by_key(|a| std::cmp::Reverse(a.created_at));
+
+    activities.truncate(count);
+
+    // Listing an activity counts as one of its polls
+    for activity in project_seed.activities.iter_mut() {
+        if activity.pending_polls > 0 && activities.iter().any(|a| a.id == activity.id) {
+            activity.pending_polls -= 1;
+        }
+    }
+    let activities: Vec<_> = activities
+        .into_iter()
+        .map(|a| a.to_json(&state.url, project))
+        .collect();
+    Json(activities).into_response()
+}
+
+pub(crate) async fn get(
+    State(state): State<Arc<MockState>>,
+    Path((project, id)): Path<(String, String)>,
+) -> Response {
+    let mut seed = state.seed();
+    let Some(activity) = seed
+        .projects
+        .get_mut(&project)
+        .and_then(|p| p.activities.iter_mut().find(|a| a.id == id))
+    else {
+        return not_found();
+    };
+
+    let current = activity.current();
+    activity.pending_polls = activity.pending_polls.saturating_sub(1);
+
+    Json(current.to_json(&state.url, &project)).into_response()
+}
+
+/// The log as JSON lines, sent in small chunks so clients have to put lines
+/// back together, and sealed once the activity is finished
+pub(crate) async fn log(
+    State(state): State<Arc<MockState>>,
+    Path((project, id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let mut seed = state.seed();
+    let Some(activity) = seed
+        .projects
+        .get_mut(&project)
+        .and_then(|p| p.activities.iter_mut().find(|a| a.id == id))
+    else {
+        return not_found();
+    };
+
+    let start_at: usize = params
+        .get("start_at")
+        .and_then(|start_at| start_at.parse().ok())
+        .unwrap_or(0);
+    let abort = activity.log_abort_after.take();
+    let end = match activity.log_interrupt_after.take().or(abort) {
+        Some(after) => after.min(activity.log.len()),
+        None => activity.log.len(),
+    };
+    let finished = end == activity.log.len()
+        && activity.pending_polls == 0
+        && (activity.state == "complete" || activity.state == "cancelled");
+
+    let mut body = String::new();
+    for (i, message) in activity.log.iter().enumerate().take(end).skip(start_at) {
+        let timestamp = activity.created_at + Duration::seconds(i as i64);
+        let line = json!({"data": {"timestamp": timestamp, "message": message}});
+        body.push_str(&format!("{}\n", line));
+    }
+    if finished {
+        body.push_str("{\"seal\": true}\n");
+    }
+
+    let mut chunks: Vec<Result<Vec<u8>, io::Error>> = body
+        .as_bytes()
+        .chunks(7)
+        .map(|chunk| Ok(chunk.to_vec()))
+        .collect();
+    let abort = abort.is_some() && !finished;
+    if abort {
+        chunks.push(Ok(b"{\"data\": {\"mess".to_vec()));
+    }
+    // Only once what came before has gone out, or the response never starts
+    let reset = stream::once(async {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        Err(io::Error::other("connection reset"))
+    })
+    .take(usize::from(abort));
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(stream::iter(chunks).chain(reset)),
+    )
+        .into_response()
+}
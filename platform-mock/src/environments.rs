@@ -0,0 +1,137 @@
+//! Environment lifecycle operations. These complete immediately, each adding a
+//! finished activity to the project.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::{error, not_found, MockState, SeedActivity, SeedEnvironment, SeedProject};
+
+pub(crate) async fn action(
+    State(state): State<Arc<MockState>>,
+    Path((project, environment, action)): Path<(String, String, String)>,
+    body: Option<Json<Value>>,
+) -> Response {
+    let body = body.map(|Json(body)| body).unwrap_or_else(|| json!({}));
+    let mut seed = state.seed();
+    let Some(project_seed) = seed.projects.get_mut(&project) else {
+        return not_found();
+    };
+    let environments = &mut project_seed.environments;
+    let Some(index) = environments.iter().position(|e| e.name == environment) else {
+        return not_found();
+    };
+
+    let status = environments[index].status.as_deref().unwrap_or("active");
+    let mut affected = vec![environment.clone()];
+    let new_status = match (action.as_str(), status) {
+        ("activate", "inactive") => "active",
+        ("deactivate", "active" | "paused") if !environments[index].is_main => "inactive",
+        ("pause", "active") => "paused",
+        ("resume", "paused") => "active",
+        ("merge" | "synchronize", "active") if environments[index].parent.is_some() => "active",
+        ("redeploy", "active") => "active",
+        ("branch", "active") => {
+            let Some(name) = body["name"].as_str() else {
+                return error(StatusCode::BAD_REQUEST, "Missing name");
+            };
+            if environments.iter().any(|e| e.name == name) {
+                return error(StatusCode::CONFLICT, "Environment already exists");
+            }
+            environments.push(SeedEnvironment {
+                name: name.to_string(),
+                parent: Some(environment.clone()),
+                ..Default::default()
+            });
+            affected.push(name.to_string());
+            "active"
+        }
+        (
+            "activate" | "deactivate" | "pause" | "resume" | "merge" | "synchronize" | "redeploy"
+            | "branch",
+            _,
+        ) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                &format!("Cannot {} a {} environment", action, status),
+            );
+        }
+        _ => return not_found(),
+    };
+    environments[index].status = Some(new_status.to_string());
+
+    accepted(
+        &state,
+        &project,
+        project_seed,
+        &format!("environment.{}", action),
+        affected,
+        body,
+    )
+}
+
+pub(crate) async fn delete(
+    State(state): State<Arc<MockState>>,
+    Path((project, environment)): Path<(String, String)>,
+) -> Response {
+    let mut seed = state.seed();
+    let Some(project_seed) = seed.projects.get_mut(&project) else {
+        return not_found();
+    };
+    let environments = &mut project_seed.environments;
+    let Some(index) = environments.iter().position(|e| e.name == environment) else {
+        return not_found();
+    };
+    if environments[index].status.as_deref().unwrap_or("active") != "inactive" {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "Environment must be deactivated before it can be deleted",
+        );
+    }
+    environments.remove(index);
+
+    accepted(
+        &state,
+        &project,
+        project_seed,
+        "environment.delete",
+        vec![environment],
+        json!({}),
+    )
+}
+
+/// Record a finished activity and answer with it the way the API does
+pub(crate) fn accepted(
+    state: &MockState,
+    project: &str,
+    project_seed: &mut SeedProject,
+    r#type: &str,
+    environments: Vec<String>,
+    parameters: Value,
+) -> Response {
+    let now = Utc::now();
+    let activity = SeedActivity {
+        id: format!("act-{}", project_seed.activities.len() + 1),
+        r#type: r#type.to_string(),
+        environments,
+        parameters,
+        created_at: now,
+        completed_at: Some(now),
+        ..Default::default()
+    };
+    let json = activity.to_json(&state.url, project);
+    project_seed.activities.push(activity);
+
+    let body = json!({
+        "status": "success",
+        "code": 202,
+        "_embedded": {"activities": [json]},
+    });
+    (StatusCode::ACCEPTED, Json(body)).into_response()
+}
@@ -0,0 +1,514 @@
+//! In-process fake of the Platform.sh API for integration tests.
+//!
+//! Serves the OAuth2 token endpoint plus organizations, subscriptions,
+//! projects, environments, git objects and variables from a [`Seed`], with
+//! HAL paging and injectable faults.
+
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::{net::TcpListener, task::JoinHandle};
+
+mod activities;
+mod environments;
+mod seed;
+mod variables;
+
+pub use crate::seed::*;
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+
+pub struct MockServer {
+    url: String,
+    state: Arc<MockState>,
+    handle: JoinHandle<()>,
+}
+
+struct MockState {
+    url: String,
+    page_size: usize,
+    // Behind a lock as environments, variables and activities change
+    seed: Mutex<Seed>,
+    git: HashMap<String, Git>,
+    faults: Mutex<Vec<Fault>>,
+    requests: Mutex<Vec<String>>,
+    tokens: Mutex<Vec<String>>,
+}
+
+type Params = Query<HashMap<String, String>>;
+
+impl MockServer {
+    /// Start serving `seed` on a random local port
+    pub async fn start(seed: Seed) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let prefix = seed.path_prefix.clone();
+
+        let git = seed
+            .projects
+            .iter()
+            .map(|(id, project)| (id.clone(), Git::build(&project.files)))
+            .collect();
+        let state = Arc::new(MockState {
+            url: url.clone(),
+            page_size: seed.page_size.unwrap_or(DEFAULT_PAGE_SIZE).max(1),
+            faults: Mutex::new(seed.faults.clone()),
+            seed: Mutex::new(seed),
+            git,
+            requests: Mutex::new(Vec::new()),
+            tokens: Mutex::new(Vec::new()),
+        });
+
+        let app = Router::new()
+            .route("/oauth2/token", post(token))
+            .route("/organizations", get(organizations))
+            .route("/organizations/{id}/subscriptions", get(subscriptions))
+            .route("/organizations/{id}/projects", get(organization_projects))
+            .route("/projects/{project}", get(project))
+            .route("/projects/{project}/domains", get(domains))
+            .route("/projects/{project}/environments", get(environments))
+            .route(
+                "/projects/{project}/environments/{environment}",
+                get(environment).delete(environments::delete),
+            )
+            .route(
+                "/projects/{project}/environments/{environment}/activities",
+                get(activities::list_environment),
+            )
+            .route(
+                "/projects/{project}/environments/{environment}/{action}",
+                post(environments::action),
+            )
+            .route("/projects/{project}/activities", get(activities::list))
+            .route("/projects/{project}/activities/{id}", get(activities::get))
+            .route(
+                "/projects/{project}/activities/{id}/log",
+                get(activities::log),
+            )
+            .route(
+                "/projects/{project}/variables",
+                get(variables::list).post(variables::create),
+            )
+            .route(
+                "/projects/{project}/variables/{name}",
+                get(variables::get)
+                    .patch(variables::update)
+                    .delete(variables::delete),
+            )
+            .route(
+                "/projects/{project}/environments/{environment}/variables",
+                get(variables::list_environment).post(variables::create_environment),
+            )
+            .route(
+                "/projects/{project}/environments/{environment}/variables/{name}",
+                get(variables::get_environment)
+                    .patch(variables::update_environment)
+                    .delete(variables::delete_environment),
+            )
+            .route("/projects/{project}/git/commits/{sha}", get(git_commit))
+            .route("/projects/{project}/git/trees/{sha}", get(git_tree))
+            .route("/projects/{project}/git/blobs/{sha}", get(git_blob))
+            .layer(middleware::from_fn_with_state(state.clone(), intercept))
+            .with_state(state.clone());
+        let (url, app) = match prefix {
+            Some(prefix) => (
+                format!("{}{}", url, prefix),
+                Router::new().nest(&prefix, app),
+            ),
+            None => (url, app),
+        };
+
+        let handle = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        MockServer { url, state, handle }
+    }
+
+    /// Base url, for both `api_url` and `auth_url`
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Every request received so far, as "METHOD /path?query"
+    pub fn requests(&self) -> Vec<String> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// Number of access tokens issued so far
+    pub fn tokens_issued(&self) -> usize {
+        self.state.tokens.lock().unwrap().len()
+    }
+}
+
+impl MockState {
+    fn seed(&self) -> MutexGuard<'_, Seed> {
+        self.seed.lock().unwrap()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Error body in the shape the real API uses
+fn error(status: StatusCode, message: &str) -> Response {
+    let body = json!({
+        "status": "error",
+        "code": status.as_u16(),
+        "message": message,
+        "title": status.canonical_reason(),
+        "detail": {},
+    });
+    (status, Json(body)).into_response()
+}
+
+fn not_found() -> Response {
+    error(StatusCode::NOT_FOUND, "Not found")
+}
+
+/// Log the request, apply faults and check the access token
+async fn intercept(State(state): State<Arc<MockState>>, request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    let method = request.method().to_string();
+    state
+        .requests
+        .lock()
+        .unwrap()
+        .push(format!("{} {}", method, request.uri()));
+
+    let fault = {
+        let mut faults = state.faults.lock().unwrap();
+        faults
+            .iter_mut()
+            .find(|fault| {
+                fault.path == path
+                    && fault
+                        .method
+                        .as_ref()
+                        .is_none_or(|m| m.eq_ignore_ascii_case(&method))
+                    && fault.times != Some(0)
+            })
+            .map(|fault| {
+                if let Some(times) = fault.times.as_mut() {
+                    *times -= 1;
+                }
+                fault.clone()
+            })
+    };
+    if let Some(fault) = fault {
+        let status = StatusCode::from_u16(fault.status).unwrap();
+        let message = fault.message.as_deref().unwrap_or("Injected fault");
+        let mut response = error(status, message);
+        if let Some(retry_after) = fault.retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        return response;
+    }
+
+    if path != "/oauth2/token" {
+        let authorized = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| state.tokens.lock().unwrap().iter().any(|t| t == token));
+        if !authorized {
+            return error(StatusCode::UNAUTHORIZED, "Invalid access token");
+        }
+    }
+
+    next.run(request).await
+}
+
+async fn token(
+    State(state): State<Arc<MockState>>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Response {
+    let seed = state.seed();
+    if let Some(api_token) = &seed.api_token {
+        if form.get("api_token") != Some(api_token) {
+            let body = json!({
+                "error": "invalid_grant",
+                "error_description": "Invalid API token.",
+            });
+            return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+        }
+    }
+
+    let mut tokens = state.tokens.lock().unwrap();
+    let access_token = format!("mock-token-{}", tokens.len() + 1);
+    tokens.push(access_token.clone());
+
+    Json(json!({
+        "access_token": access_token,
+        "expires_in": seed.token_expires_in.unwrap_or(900),
+        "token_type": "bearer",
+    }))
+    .into_response()
+}
+
+/// One page of `items` as a HAL collection, linking to the next page
+fn page(
+    state: &MockState,
+    path: &str,
+    params: &HashMap<String, String>,
+    items: Vec<Value>,
+) -> Response {
+    let size = state.page_size;
+    let number: usize = params
+        .get("page")
+        .and_then(|page| page.parse().ok())
+        .unwrap_or(1)
+        .max(1);
+
+    let link = |number: usize| {
+        let mut query: Vec<String> = params
+            .iter()
+            .filter(|(key, _)| *key != "page")
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        query.sort();
+        query.push(format!("page={}", number));
+        json!({"href": format!("{}{}?{}", state.url, path, query.join("&"))})
+    };
+
+    let count = items.len();
+    let mut links = json!({"self": link(number)});
+    if number * size < count {
+        links["next"] = link(number + 1);
+    }
+    let items: Vec<Value> = items
+        .into_iter()
+        .skip((number - 1) * size)
+        .take(size)
+        .collect();
+
+    Json(json!({"count": count, "items": items, "_links": links})).into_response()
+}
+
+async fn organizations(State(state): State<Arc<MockState>>, Query(params): Params) -> Response {
+    let items = state
+        .seed()
+        .organizations
+        .iter()
+        .map(SeedOrganization::to_json)
+        .collect();
+    page(&state, "/organizations", &params, items)
+}
+
+async fn subscriptions(
+    State(state): State<Arc<MockState>>,
+    Path(id): Path<String>,
+    Query(params): Params,
+) -> Response {
+    let seed = state.seed();
+    let Some(organization) = seed.organizations.iter().find(|o| o.id == id) else {
+        return not_found();
+    };
+    let items = organization
+        .subscriptions
+        .iter()
+        .map(SeedSubscription::to_json)
+        .collect();
+    page(
+        &state,
+        &format!("/organizations/{}/subscriptions", id),
+        &params,
+        items,
+    )
+}
+
+fn project_json(state: &MockState, id: &str) -> Option<Value> {
+    let seed = state.seed();
+    let project = seed.projects.get(id)?;
+    let subscription = seed
+        .organizations
+        .iter()
+        .flat_map(|o| o.subscriptions.iter().map(move |s| (o, s)))
+        .find(|(_, s)| s.project_id == id);
+
+    let title = project
+        .title
+        .clone()
+        .or_else(|| subscription.map(|(_, s)| s.title.clone()))
+        .unwrap_or_else(|| id.to_string());
+    let organization = subscription.map(|(o, _)| o.id.clone()).unwrap_or_default();
+    let region = subscription
+        .and_then(|(_, s)| s.region.clone())
+        .unwrap_or_else(|| "eu-3.platform.sh".to_string());
+    let default_branch = project
+        .environments
+        .iter()
+        .find(|e| e.is_main)
+        .map(|e| e.name.clone());
+
+    Some(json!({
+        "id": id,
+        "created_at": "2024-01-01T12:00:00+00:00",
+        "updated_at": "2024-01-01T12:00:00+00:00",
+        "attributes": {},
+        "title": title,
+        "description": "",
+        "namespace": "platformsh",
+        "organization": organization,
+        "organization_id": organization,
+        "default_branch": default_branch,
+        "status": {"code": "provisioned", "message": "ok"},
+        "timezone": "Europe/Copenhagen",
+        "region": region,
+        "repository": {
+            "url": format!("{}@git.eu-3.platform.sh:{}.git", id, id),
+            "client_ssh_key": "ssh-rsa AAAA mock",
+        },
+        "default_domain": project.default_domain,
+        "_links": {"self": {"href": format!("{}/projects/{}", state.url, id)}},
+    }))
+}
+
+async fn organization_projects(
+    State(state): State<Arc<MockState>>,
+    Path(id): Path<String>,
+    Query(params): Params,
+) -> Response {
+    let seed = state.seed();
+    let Some(organization) = seed.organizations.iter().find(|o| o.id == id) else {
+        return not_found();
+    };
+    let items = organization
+        .subscriptions
+        .iter()
+        .map(|subscription| {
+            let default_branch = seed
+                .projects
+                .get(&subscription.project_id)
+                .and_then(|p| p.environments.iter().find(|e| e.is_main))
+                .map(|e| e.name.as_str());
+            subscription.to_project_json(&id, default_branch)
+        })
+        .collect();
+    page(
+        &state,
+        &format!("/organizations/{}/projects", id),
+        &params,
+        items,
+    )
+}
+
+async fn project(State(state): State<Arc<MockState>>, Path(project): Path<String>) -> Response {
+    match project_json(&state, &project) {
+        Some(project) => Json(project).into_response(),
+        None => not_found(),
+    }
+}
+
+async fn domains(State(state): State<Arc<MockState>>, Path(project_id): Path<String>) -> Response {
+    let seed = state.seed();
+    let Some(project) = seed.projects.get(&project_id) else {
+        return not_found();
+    };
+    let domains: Vec<Value> = project
+        .domains
+        .iter()
+        .map(|name| {
+            json!({
+                "id": name,
+                "name": name,
+                "project": project_id,
+                "type": "prod",
+                "is_default": project.default_domain.as_ref() == Some(name),
+                "created_at": "2024-01-01T12:00:00+00:00",
+                "updated_at": "2024-01-01T12:00:00+00:00",
+            })
+        })
+        .collect();
+    Json(domains).into_response()
+}
+
+fn environments_json(state: &MockState, project_id: &str) -> Option<Vec<Value>> {
+    let seed = state.seed();
+    let project = seed.projects.get(project_id)?;
+    let head_commit = state
+        .git
+        .get(project_id)
+        .and_then(|git| git.commit.as_deref());
+
+    Some(
+        project
+            .environments
+            .iter()
+            .map(|environment| environment.to_json(project_id, head_commit))
+            .collect(),
+    )
+}
+
+async fn environments(
+    State(state): State<Arc<MockState>>,
+    Path(project): Path<String>,
+) -> Response {
+    match environments_json(&state, &project) {
+        Some(environments) => Json(environments).into_response(),
+        None => not_found(),
+    }
+}
+
+async fn environment(
+    State(state): State<Arc<MockState>>,
+    Path((project, environment)): Path<(String, String)>,
+) -> Response {
+    environments_json(&state, &project)
+        .and_then(|environments| {
+            environments
+                .into_iter()
+                .find(|e| e["name"] == environment.as_str())
+        })
+        .map(|environment| Json(environment).into_response())
+        .unwrap_or_else(not_found)
+}
+
+fn git_object(
+    state: &MockState,
+    project: &str,
+    objects: fn(&Git) -> &HashMap<String, Value>,
+    sha: &str,
+) -> Response {
+    state
+        .git
+        .get(project)
+        .and_then(|git| objects(git).get(sha))
+        .map(|object| Json(object.clone()).into_response())
+        .unwrap_or_else(not_found)
+}
+
+async fn git_commit(
+    State(state): State<Arc<MockState>>,
+    Path((project, sha)): Path<(String, String)>,
+) -> Response {
+    git_object(&state, &project, |git| &git.commits, &sha)
+}
+
+async fn git_tree(
+    State(state): State<Arc<MockState>>,
+    Path((project, sha)): Path<(String, String)>,
+) -> Response {
+    git_object(&state, &project, |git| &git.trees, &sha)
+}
+
+async fn git_blob(
+    State(state): State<Arc<MockState>>,
+    Path((project, sha)): Path<(String, String)>,
+) -> Response {
+    git_object(&state, &project, |git| &git.blobs, &sha)
+}
@@ -0,0 +1,409 @@
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+const OWNER: &str = "b63f0b34-8821-4323-a05f-db70eff34304";
+const TIMESTAMP: &str = "2024-01-01T12:00:00+00:00";
+
+/// Declarative description of everything the mock API serves. Only the
+/// interesting fields need to be given, the rest of each API object is
+/// filled in with plausible defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Seed {
+    /// Items per page for paged collections, to exercise pagination
+    pub page_size: Option<usize>,
+    /// If set, the token endpoint only accepts this API token
+    pub api_token: Option<String>,
+    /// Lifetime of issued access tokens in seconds
+    pub token_expires_in: Option<i32>,
+    /// Serve everything under this path, like a proxy would, while links
+    /// still point at the root as the API behind it knows nothing of it
+    pub path_prefix: Option<String>,
+    pub organizations: Vec<SeedOrganization>,
+    /// Keyed by project id
+    pub projects: BTreeMap<String, SeedProject>,
+    /// Errors returned instead of the real response
+    pub faults: Vec<Fault>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeedOrganization {
+    pub id: String,
+    pub name: String,
+    pub subscriptions: Vec<SeedSubscription>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeedSubscription {
+    pub id: Option<String>,
+    pub project_id: String,
+    pub title: String,
+    pub plan: Option<String>,
+    pub storage: Option<i32>,
+    pub region: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeedProject {
+    pub title: Option<String>,
+    pub default_domain: Option<String>,
+    /// Domains attached to the project, `default_domain` being the default one
+    pub domains: Vec<String>,
+    pub environments: Vec<SeedEnvironment>,
+    /// Content of the git repository at the head commit of every
+    /// environment, keyed by path, e.g. `.platform/services.yaml`
+    pub files: BTreeMap<String, String>,
+    pub variables: Vec<SeedVariable>,
+    /// Keyed by environment name
+    pub environment_variables: BTreeMap<String, Vec<SeedVariable>>,
+    /// Oldest first. Environment operations add to these.
+    pub activities: Vec<SeedActivity>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeedEnvironment {
+    pub name: String,
+    pub is_main: bool,
+    pub status: Option<String>,
+    pub parent: Option<String>,
+    /// Defaults to the commit built from the project's `files`
+    pub head_commit: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeedVariable {
+    pub name: String,
+    pub value: Option<String>,
+    pub is_json: bool,
+    pub is_sensitive: bool,
+    pub visible_build: bool,
+    pub visible_runtime: bool,
+    pub inherited: bool,
+    pub is_enabled: bool,
+    pub is_inheritable: bool,
+}
+
+impl Default for SeedVariable {
+    fn default() -> Self {
+        SeedVariable {
+            name: String::new(),
+            value: None,
+            is_json: false,
+            is_sensitive: false,
+            visible_build: true,
+            visible_runtime: true,
+            inherited: false,
+            is_enabled: true,
+            is_inheritable: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeedActivity {
+    pub id: String,
+    pub r#type: String,
+    /// "pending", "in_progress", "complete" or "cancelled"
+    pub state: String,
+    /// "success" or "failure" once complete
+    pub result: Option<String>,
+    pub environments: Vec<String>,
+    pub parameters: Value,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Reported as in progress for this many requests, before `state`
+    pub pending_polls: usize,
+    /// Messages of the activity log
+    pub log: Vec<String>,
+    /// Drop the first connection to the log after this many records
+    pub log_interrupt_after: Option<usize>,
+    /// Abort the first connection to the log after this many records, in the
+    /// middle of the next one and without ending the chunked body
+    pub log_abort_after: Option<usize>,
+}
+
+impl Default for SeedActivity {
+    fn default() -> Self {
+        SeedActivity {
+            id: String::new(),
+            r#type: String::new(),
+            state: "complete".to_string(),
+            result: Some("success".to_string()),
+            environments: Vec::new(),
+            parameters: json!({}),
+            description: None,
+            created_at: Utc::now(),
+            completed_at: None,
+            pending_polls: 0,
+            log: Vec::new(),
+            log_interrupt_after: None,
+            log_abort_after: None,
+        }
+    }
+}
+
+/// Answer requests for `path` with an error instead of the seeded data
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fault {
+    /// Request path, without query string, e.g. `/organizations`
+    pub path: String,
+    pub method: Option<String>,
+    pub status: u16,
+    pub message: Option<String>,
+    /// Value of the Retry-After header, in seconds
+    pub retry_after: Option<u64>,
+    /// Only fail this many times, then serve the real response. Fails
+    /// forever if not set.
+    pub times: Option<usize>,
+}
+
+impl Seed {
+    pub fn from_yaml(yaml: &str) -> Result<Seed, serde_yaml::Error> {
+        serde_yaml::from_str(yaml)
+    }
+}
+
+impl SeedOrganization {
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "owner_id": OWNER,
+            "namespace": "platformsh",
+            "name": self.name,
+            "label": self.name,
+            "country": "DK",
+            "created_at": TIMESTAMP,
+            "updated_at": TIMESTAMP,
+        })
+    }
+}
+
+impl SeedSubscription {
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "id": self.id.clone().unwrap_or_else(|| format!("sub-{}", self.project_id)),
+            "status": "active",
+            "created_at": TIMESTAMP,
+            "owner": OWNER,
+            "plan": self.plan.as_deref().unwrap_or("standard"),
+            "environments": 3,
+            "storage": self.storage.unwrap_or(5120),
+            "project_id": self.project_id,
+            "project_title": self.title,
+            "project_region": self.region.as_deref().unwrap_or("eu-3.platform.sh"),
+            "project_region_label": "Europe (Ireland)",
+            "project_ui": format!("https://console.platform.sh/projects/{}", self.project_id),
+        })
+    }
+
+    /// The project as listed by `/organizations/{id}/projects`
+    pub(crate) fn to_project_json(
+        &self,
+        organization_id: &str,
+        default_branch: Option<&str>,
+    ) -> Value {
+        json!({
+            "id": self.project_id,
+            "organization_id": organization_id,
+            "subscription_id": self.id.clone().unwrap_or_else(|| format!("sub-{}", self.project_id)),
+            "vendor": "platformsh",
+            "region": self.region.as_deref().unwrap_or("eu-3.platform.sh"),
+            "title": self.title,
+            "type": "grid",
+            "plan": self.plan.as_deref().unwrap_or("standard"),
+            "timezone": "Europe/Copenhagen",
+            "default_branch": default_branch,
+            "status": "active",
+            "project_ui": format!("https://console.platform.sh/projects/{}", self.project_id),
+            "created_at": TIMESTAMP,
+            "updated_at": TIMESTAMP,
+        })
+    }
+}
+
+impl SeedEnvironment {
+    pub(crate) fn to_json(&self, project_id: &str, head_commit: Option<&str>) -> Value {
+        let machine_name = format!("{}-abc123", self.name);
+        json!({
+            "id": self.name,
+            "created_at": TIMESTAMP,
+            "updated_at": TIMESTAMP,
+            "name": self.name,
+            "machine_name": machine_name,
+            "title": self.name,
+            "edge_hostname": format!("{}-{}.eu-3.platformsh.site", machine_name, project_id),
+            "attributes": {},
+            "type": if self.is_main { "production" } else { "development" },
+            "parent": self.parent,
+            "clone_parent_on_create": true,
+            "deployment_target": "local",
+            "status": self.status.as_deref().unwrap_or("active"),
+            "is_dirty": false,
+            "is_main": self.is_main,
+            "is_pr": false,
+            "has_code": true,
+            "has_deployment": true,
+            "last_backup_at": if self.is_main { Some(TIMESTAMP) } else { None },
+            "last_active_at": TIMESTAMP,
+            "head_commit": self.head_commit.as_deref().or(head_commit),
+        })
+    }
+}
+
+impl SeedVariable {
+    pub(crate) fn to_json(&self, project_id: &str, environment: Option<&str>) -> Value {
+        let mut variable = json!({
+            "id": self.name,
+            "name": self.name,
+            "attributes": {},
+            // Sensitive values are never returned by the API
+            "value": if self.is_sensitive { None } else { self.value.clone() },
+            "is_json": self.is_json,
+            "is_sensitive": self.is_sensitive,
+            "visible_build": self.visible_build,
+            "visible_runtime": self.visible_runtime,
+            "project": project_id,
+            "created_at": TIMESTAMP,
+            "updated_at": TIMESTAMP,
+        });
+        if let Some(environment) = environment {
+            variable["environment"] = json!(environment);
+            variable["inherited"] = json!(self.inherited);
+            variable["is_enabled"] = json!(self.is_enabled);
+            variable["is_inheritable"] = json!(self.is_inheritable);
+        }
+
+        variable
+    }
+}
+
+impl SeedActivity {
+    /// As reported right now, in progress while `pending_polls` are left
+    pub(crate) fn current(&self) -> SeedActivity {
+        let mut current = self.clone();
+        if self.pending_polls > 0 {
+            current.state = "in_progress".to_string();
+            current.result = None;
+        }
+        current
+    }
+
+    pub(crate) fn to_json(&self, url: &str, project_id: &str) -> Value {
+        let completed_at = match self.state.as_str() {
+            "complete" | "cancelled" => Some(self.completed_at.unwrap_or(self.created_at)),
+            _ => None,
+        };
+        let description = self
+            .description
+            .clone()
+            .unwrap_or_else(|| format!("<user>Mock</user> ran {}", self.r#type));
+        let href = format!("{}/projects/{}/activities/{}", url, project_id, self.id);
+
+        json!({
+            "id": self.id,
+            "created_at": self.created_at,
+            "updated_at": completed_at.unwrap_or(self.created_at),
+            "type": self.r#type,
+            "parameters": self.parameters,
+            "project": project_id,
+            "environments": self.environments,
+            "state": self.state,
+            "result": self.result,
+            "started_at": self.created_at,
+            "completed_at": completed_at,
+            "completion_percent": if completed_at.is_some() { 100 } else { 0 },
+            "cancelled_at": if self.state == "cancelled" { completed_at } else { None },
+            "timings": {},
+            "description": description,
+            "text": description,
+            "payload": {},
+            "_links": {
+                "self": {"href": href},
+                "log": {"href": format!("{}/log", href)},
+            },
+        })
+    }
+}
+
+/// Git objects built from a project's `files`
+#[derive(Debug, Default)]
+pub(crate) struct Git {
+    pub commit: Option<String>,
+    pub commits: HashMap<String, Value>,
+    pub trees: HashMap<String, Value>,
+    pub blobs: HashMap<String, Value>,
+}
+
+impl Git {
+    pub(crate) fn build(files: &BTreeMap<String, String>) -> Git {
+        let mut git = Git::default();
+        if files.is_empty() {
+            return git;
+        }
+
+        let entries: Vec<(&str, &str)> = files
+            .iter()
+            .map(|(path, content)| (path.trim_start_matches('/'), content.as_str()))
+            .collect();
+        let tree = git.add_tree(&entries);
+        let commit = sha1(format!("commit {}", tree).as_bytes());
+        git.commits.insert(
+            commit.clone(),
+            json!({"id": commit, "sha": commit, "tree": tree}),
+        );
+        git.commit = Some(commit);
+
+        git
+    }
+
+    /// Add the tree for `entries` (paths relative to it) and all trees and
+    /// blobs below it, returning its SHA
+    fn add_tree(&mut self, entries: &[(&str, &str)]) -> String {
+        let mut items = Vec::new();
+        let mut subtrees: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+
+        for (path, content) in entries {
+            match path.split_once('/') {
+                Some((dir, rest)) => subtrees.entry(dir).or_default().push((rest, content)),
+                None => {
+                    let sha = sha1(format!("blob {}\0{}", content.len(), content).as_bytes());
+                    self.blobs.insert(
+                        sha.clone(),
+                        json!({
+                            "sha": sha,
+                            "size": content.len(),
+                            "encoding": "base64",
+                            "content": general_purpose::STANDARD.encode(content),
+                        }),
+                    );
+                    items.push(json!({"path": path, "mode": "100644", "type": "blob", "sha": sha}));
+                }
+            }
+        }
+        for (dir, entries) in subtrees {
+            let sha = self.add_tree(&entries);
+            items.push(json!({"path": dir, "mode": "040000", "type": "tree", "sha": sha}));
+        }
+
+        let sha = sha1(Value::Array(items.clone()).to_string().as_bytes());
+        self.trees
+            .insert(sha.clone(), json!({"id": sha, "sha": sha, "tree": items}));
+
+        sha
+    }
+}
+
+fn sha1(data: &[u8]) -> String {
+    sha1_smol::Sha1::from(data).digest().to_string()
+}
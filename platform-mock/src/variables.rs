@@ -0,0 +1,276 @@
+//! Project and environment variables. Sensitive values are stored, but like
+//! the real API never returned.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::{environments::accepted, error, not_found, MockState, SeedVariable};
+
+/// Body of POST and PATCH requests
+#[derive(Debug, Deserialize)]
+pub(crate) struct VariableBody {
+    name: Option<String>,
+    value: Option<String>,
+    is_json: Option<bool>,
+    is_sensitive: Option<bool>,
+    visible_build: Option<bool>,
+    visible_runtime: Option<bool>,
+    is_enabled: Option<bool>,
+    is_inheritable: Option<bool>,
+}
+
+impl VariableBody {
+    fn apply(self, variable: &mut SeedVariable) {
+        if let Some(value) = self.value {
+            variable.value = Some(value);
+        }
+        variable.is_json = self.is_json.unwrap_or(variable.is_json);
+        variable.is_sensitive = self.is_sensitive.unwrap_or(variable.is_sensitive);
+        variable.visible_build = self.visible_build.unwrap_or(variable.visible_build);
+        variable.visible_runtime = self.visible_runtime.unwrap_or(variable.visible_runtime);
+        variable.is_enabled = self.is_enabled.unwrap_or(variable.is_enabled);
+        variable.is_inheritable = self.is_inheritable.unwrap_or(variable.is_inheritable);
+    }
+}
+
+/// Where a variable lives - a project, or one of its environments
+struct Level<'a> {
+    project: &'a str,
+    environment: Option<&'a str>,
+}
+
+impl Level<'_> {
+    fn activity_type(&self, action: &str) -> String {
+        match self.environment {
+            Some(_) => format!("environment.variable.{}", action),
+            None => format!("project.variable.{}", action),
+        }
+    }
+
+    /// Run `f` on the variables at this level, or answer 404 if there is no
+    /// such project or environment
+    fn with_variables(
+        &self,
+        state: &MockState,
+        f: impl FnOnce(&mut Vec<SeedVariable>) -> Result<(&'static str, Value), StatusCode>,
+    ) -> Response {
+        let mut seed = state.seed();
+        let Some(project_seed) = seed.projects.get_mut(self.project) else {
+            return not_found();
+        };
+        let variables = match self.environment {
+            Some(environment) => {
+                if !project_seed
+                    .environments
+                    .iter()
+                    .any(|e| e.name == environment)
+                {
+                    return not_found();
+                }
+                project_seed
+                    .environment_variables
+                    .entry(environment.to_string())
+                    .or_default()
+            }
+            None => &mut project_seed.variables,
+        };
+
+        match f(variables) {
+            Ok((action, parameters)) => {
+                let environments = self.environment.map(str::to_string).into_iter().collect();
+                accepted(
+                    state,
+                    self.project,
+                    project_seed,
+                    &self.activity_type(action),
+                    environments,
+                    parameters,
+                )
+            }
+            Err(StatusCode::CONFLICT) => error(StatusCode::CONFLICT, "Variable already exists"),
+            Err(status) => error(status, status.canonical_reason().unwrap_or_default()),
+        }
+    }
+
+    fn list(&self, state: &MockState) -> Response {
+        let seed = state.seed();
+        let Some(project_seed) = seed.projects.get(self.project) else {
+            return not_found();
+        };
+        let variables = match self.environment {
+            Some(environment) => {
+                if !project_seed
+                    .environments
+                    .iter()
+                    .any(|e| e.name == environment)
+                {
+                    return not_found();
+                }
+                project_seed.environment_variables.get(environment)
+            }
+            None => Some(&project_seed.variables),
+        };
+
+        let variables: Vec<Value> = variables
+            .into_iter()
+            .flatten()
+            .map(|variable| variable.to_json(self.project, self.environment))
+            .collect();
+        Json(variables).into_response()
+    }
+
+    fn get(&self, state: &MockState, name: &str) -> Response {
+        let seed = state.seed();
+        let variable = seed.projects.get(self.project).and_then(|p| {
+            match self.environment {
+                Some(environment) => p.environment_variables.get(environment),
+                None => Some(&p.variables),
+            }
+            .and_then(|variables| variables.iter().find(|v| v.name == name))
+        });
+
+        match variable {
+            Some(variable) => {
+                Json(variable.to_json(self.project, self.environment)).into_response()
+            }
+            None => not_found(),
+        }
+    }
+
+    fn create(&self, state: &MockState, body: VariableBody) -> Response {
+        let Some(name) = body.name.clone() else {
+            return error(StatusCode::BAD_REQUEST, "Missing name");
+        };
+        let mut response = self.with_variables(state, |variables| {
+            if variables.iter().any(|v| v.name == name) {
+                return Err(StatusCode::CONFLICT);
+            }
+            let mut variable = SeedVariable {
+                name: name.clone(),
+                ..Default::default()
+            };
+            body.apply(&mut variable);
+            variables.push(variable);
+            Ok(("create", json!({"variable": name})))
+        });
+        if response.status() == StatusCode::ACCEPTED {
+            *response.status_mut() = StatusCode::CREATED;
+        }
+        response
+    }
+
+    fn update(&self, state: &MockState, name: &str, body: VariableBody) -> Response {
+        self.with_variables(state, |variables| {
+            let Some(variable) = variables.iter_mut().find(|v| v.name == name) else {
+                return Err(StatusCode::NOT_FOUND);
+            };
+            body.apply(variable);
+            Ok(("update", json!({"variable": name})))
+        })
+    }
+
+    fn delete(&self, state: &MockState, name: &str) -> Response {
+        self.with_variables(state, |variables| {
+            let Some(index) = variables.iter().position(|v| v.name == name) else {
+                return Err(StatusCode::NOT_FOUND);
+            };
+            variables.remove(index);
+            Ok(("delete", json!({"variable": name})))
+        })
+    }
+}
+
+fn project_level(project: &str) -> Level<'_> {
+    Level {
+        project,
+        environment: None,
+    }
+}
+
+fn environment_level<'a>(project: &'a str, environment: &'a str) -> Level<'a> {
+    Level {
+        project,
+        environment: Some(environment),
+    }
+}
+
+pub(crate) async fn list(
+    State(state): State<Arc<MockState>>,
+    Path(project): Path<String>,
+) -> Response {
+    project_level(&project).list(&state)
+}
+
+pub(crate) async fn get(
+    State(state): State<Arc<MockState>>,
+    Path((project, name)): Path<(String, String)>,
+) -> Response {
+    project_level(&project).get(&state, &name)
+}
+
+pub(crate) async fn create(
+    State(state): State<Arc<MockState>>,
+    Path(project): Path<String>,
+    Json(body): Json<VariableBody>,
+) -> Response {
+    project_level(&project).create(&state, body)
+}
+
+pub(crate) async fn update(
+    State(state): State<Arc<MockState>>,
+    Path((project, name)): Path<(String, String)>,
+    Json(body): Json<VariableBody>,
+) -> Response {
+    project_level(&project).update(&state, &name, body)
+}
+
+pub(crate) async fn delete(
+    State(state): State<Arc<MockState>>,
+    Path((project, name)): Path<(String, String)>,
+) -> Response {
+    project_level(&project).delete(&state, &name)
+}
+
+pub(crate) async fn list_environment(
+    State(state): State<Arc<MockState>>,
+    Path((project, environment)): Path<(String, String)>,
+) -> Response {
+    environment_level(&project, &environment).list(&state)
+}
+
+pub(crate) async fn get_environment(
+    State(state): State<Arc<MockState>>,
+    Path((project, environment, name)): Path<(String, String, String)>,
+) -> Response {
+    environment_level(&project, &environment).get(&state, &name)
+}
+
+pub(crate) async fn create_environment(
+    State(state): State<Arc<MockState>>,
+    Path((project, environment)): Path<(String, String)>,
+    Json(body): Json<VariableBody>,
+) -> Response {
+    environment_level(&project, &environment).create(&state, body)
+}
+
+pub(crate) async fn update_environment(
+    State(state): State<Arc<MockState>>,
+    Path((project, environment, name)): Path<(String, String, String)>,
+    Json(body): Json<VariableBody>,
+) -> Response {
+    environment_level(&project, &environment).update(&state, &name, body)
+}
+
+pub(crate) async fn delete_environment(
+    State(state): State<Arc<MockState>>,
+    Path((project, environment, name)): Path<(String, String, String)>,
+) -> Response {
+    environment_level(&project, &environment).delete(&state, &name)
+}
@@ -1,18 +1,54 @@
-use base64::{engine::general_purpose, Engine as _};
 use chrono::{DateTime, Local};
 use clap::Parser;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 //use tracing_subscriber::{layer::SubscriberExt, registry::Registry};
-use std::{collections::HashMap, fs::File, io, str};
-use tracing::{error, info, span, warn};
-
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    fs::File,
+    io,
+    path::PathBuf,
+    process::ExitCode,
+};
+use tracing::info;
+
+mod output;
 mod php_composer;
+mod scan;
+
+/// A package to report the version of, either just its name or with an
+/// advisory:
+///
+/// ```yaml
+/// - name: drupal/swiftmailer
+///   advisory: SA-CONTRIB-2024-006
+///   affected: ">=2.0 <2.4.1 || ^3.0"
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Package {
+    Name(String),
+    Advisory {
+        name: String,
+        advisory: String,
+        /// Composer constraint matching the affected versions
+        affected: String,
+    },
+}
+
+impl Package {
+    fn name(&self) -> &str {
+        match self {
+            Package::Name(name) => name,
+            Package::Advisory { name, .. } => name,
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Config {
     frameworks: Option<HashMap<String, Vec<String>>>,
-    packages: Option<Vec<String>>,
+    packages: Option<Vec<Package>>,
 }
 
 impl Config {
@@ -20,8 +56,8 @@ impl Config {
         let mut map: HashMap<String, String> = HashMap::new();
 
         if let Some(packages) = &self.packages {
-            for name in packages.iter() {
-                map.insert(name.to_string(), name.to_string());
+            for package in packages.iter() {
+                map.insert(package.name().to_string(), package.name().to_string());
             }
         }
 
@@ -47,13 +83,35 @@ impl Config {
         }
 
         if let Some(packages) = &self.packages {
-            for name in packages.iter() {
-                cols.push(name.to_string());
+            for package in packages.iter() {
+                // A package may be listed once per advisory
+                if !cols.iter().any(|col| col == package.name()) {
+                    cols.push(package.name().to_string());
+                }
             }
         }
 
         cols
     }
+
+    fn advisories(&self) -> Result<Vec<php_composer::Advisory>, php_composer::ParseError> {
+        let mut advisories = Vec::new();
+        for package in self.packages.iter().flatten() {
+            if let Package::Advisory {
+                name,
+                advisory,
+                affected,
+            } = package
+            {
+                advisories.push(php_composer::Advisory {
+                    id: advisory.to_string(),
+                    package: name.to_string(),
+                    affected: affected.parse()?,
+                });
+            }
+        }
+        Ok(advisories)
+    }
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -71,8 +129,53 @@ struct Report {
     // App
     r#type: String,
     app: String,
-    packages: HashMap<String, String>,
-    services: HashMap<String, String>,
+    packages: BTreeMap<String, String>,
+    services: BTreeMap<String, String>,
+    /// Status of the app by advisory ID
+    advisories: BTreeMap<String, php_composer::AdvisoryStatus>,
+}
+
+/// A public url of a project, and where it leads
+#[derive(Debug, Serialize, Clone)]
+struct RouteReport {
+    subscription: String,
+    title: String,
+    host: String,
+    url: String,
+    /// "upstream" or "redirect"
+    r#type: String,
+    /// App an upstream route hits
+    app: String,
+    /// Where a redirect goes
+    to: String,
+    primary: bool,
+}
+
+impl Report {
+    /// Line for a subscription, without any app details filled in
+    fn new(
+        subscription: &platform::Subscription,
+        environment: Option<&platform::Environment>,
+    ) -> Report {
+        Report {
+            subscription: subscription.project_id.to_string(),
+            title: subscription.project_title.to_string(),
+            plan: subscription.plan.to_string(),
+            storage: subscription.storage,
+            region: subscription
+                .project_region
+                .clone()
+                .unwrap_or("".to_string()),
+
+            last_backup_at: environment.and_then(|environment| environment.last_backup_at),
+
+            r#type: "".to_string(),
+            app: "".to_string(),
+            packages: BTreeMap::new(),
+            services: BTreeMap::new(),
+            advisories: BTreeMap::new(),
+        }
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -81,6 +184,14 @@ struct Args {
     #[arg(long, short, action)]
     services: bool,
 
+    /// List every route of every project, instead of the apps
+    #[arg(long, short, action)]
+    routes: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    format: output::Format,
+
     /// Project ID
     #[arg(long, short)]
     project: Vec<String>,
@@ -88,10 +199,66 @@ struct Args {
     /// Platform Access Token
     #[arg(long, env = "PLATFORMSH_CLI_TOKEN")]
     token: String,
+
+    /// API base url
+    #[arg(
+        long,
+        env = "PLATFORMSH_API_URL",
+        default_value = "https://api.platform.sh"
+    )]
+    api_url: String,
+
+    /// OAuth2 base url
+    #[arg(
+        long,
+        env = "PLATFORMSH_AUTH_URL",
+        default_value = "https://auth.api.platform.sh"
+    )]
+    auth_url: String,
+
+    /// Number of subscriptions to scan in parallel, and of blobs to fetch in
+    /// parallel within each of them
+    #[arg(long, default_value_t = 4)]
+    concurrency: usize,
+
+    /// Directory for caching git trees and blobs between runs
+    /// [default: $XDG_CACHE_HOME/platform-scan]
+    #[arg(long, env = "PLATFORM_SCAN_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Maximum size of the cache in MiB
+    #[arg(long, default_value_t = 1024)]
+    cache_size: u64,
+
+    /// Don't read or write the cache
+    #[arg(long, action)]
+    no_cache: bool,
+
+    /// Record every API response as a test fixture in this directory
+    #[arg(long, hide = true)]
+    record: Option<PathBuf>,
+}
+
+impl Args {
+    fn cache(&self) -> Option<platform::Cache> {
+        if self.no_cache {
+            return None;
+        }
+
+        let dir = match &self.cache_dir {
+            Some(dir) => dir.clone(),
+            None => env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?
+                .join("platform-scan"),
+        };
+
+        Some(platform::Cache::new(dir, self.cache_size * 1024 * 1024))
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
     let args = Args::parse();
     // tracing_subscriber::fmt::init();
     let subscriber = tracing_subscriber::fmt()
@@ -105,267 +272,85 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let file = File::open("config.yaml")?;
     let config: Config = serde_yaml::from_reader(file)?;
     let packages_map = config.packages_map();
+    let advisories = config.advisories()?;
     let mut lines: Vec<Report> = Vec::new();
 
-    let drupal = Regex::new(r"projects\[drupal\]\[version\]\s*=\s*([0-9.]+)").unwrap();
-
-    let client = platform::ApiClient::new(&args.token).await?;
+    let mut builder = platform::ApiClient::builder()
+        .api_url(&args.api_url)
+        .auth_url(&args.auth_url);
+    if let Some(dir) = &args.record {
+        builder = builder.transport(platform::Transport::Record(dir.clone()));
+    }
+    if let Some(cache) = args.cache() {
+        info!(dir = %cache.dir().display(), "caching git objects");
+        builder = builder.cache(cache);
+    }
+    let client = builder.build(&args.token).await?;
 
-    // let organizations = client.organizations().await?;
-    // eprint!("{:#?}", organizations);
+    let subscriptions = client.subscriptions().await?;
+    let subscriptions = subscriptions.iter().filter(|subscription| {
+        args.project.is_empty() || args.project.contains(&subscription.project_id)
+    });
+
+    let scanner =
+        scan::Scanner::new(client, packages_map, args.concurrency.max(1)).with_routes(args.routes);
+    // The routes inventory has no use for advisories, and no way to report
+    // them
+    let scanner = if args.routes {
+        scanner
+    } else {
+        scanner.with_advisories(advisories.clone())
+    };
+    let scans = scanner.scan(subscriptions.clone()).await?;
+
+    if args.routes {
+        let mut routes: Vec<RouteReport> = scans.into_iter().flat_map(|scan| scan.routes).collect();
+        routes.sort_by_cached_key(|x| format!("{}-{}", x.title.to_lowercase(), x.url));
+
+        let table = output::Table {
+            heading: [
+                "Subscription",
+                "Title",
+                "Host",
+                "URL",
+                "Type",
+                "App",
+                "Redirect to",
+                "Primary",
+            ]
+            .map(String::from)
+            .to_vec(),
+            rows: routes
+                .iter()
+                .map(|route| {
+                    vec![
+                        route.subscription.clone(),
+                        route.title.clone(),
+                        route.host.clone(),
+                        route.url.clone(),
+                        route.r#type.clone(),
+                        route.app.clone(),
+                        route.to.clone(),
+                        route.primary.to_string(),
+                    ]
+                })
+                .collect(),
+        };
+        output::write(args.format, io::stdout().lock(), &routes, &table)?;
+        return Ok(ExitCode::SUCCESS);
+    }
 
     let mut services_cnt = HashMap::new();
     let mut unreadable: HashMap<&String, Vec<String>> = HashMap::new();
-    let subscriptions = client.subscriptions().await?;
-    for subscription in subscriptions.iter() {
-        if !args.project.is_empty() && !args.project.contains(&subscription.project_id) {
-            continue;
+    for (subscription, scan) in subscriptions.zip(scans) {
+        for name in scan.services {
+            let count = services_cnt.entry(name).or_insert(0);
+            *count += 1;
         }
-        let _guard = span!(
-            tracing::Level::INFO,
-            "subscription",
-            id = &subscription.project_id
-        )
-        .entered();
-
-        info!(
-            subscription.project_id,
-            subscription.project_title, subscription.plan, subscription.storage
-        );
-
-        let environments_res: Result<Vec<platform::Environment>, reqwest::Error> = client
-            .get(format!(
-                "https://api.platform.sh/projects/{}/environments",
-                subscription.project_id
-            ))
-            .send()
-            .await?
-            .json()
-            .await;
-
-        if let Ok(environments) = environments_res {
-            for environment in environments.iter() {
-                // eprintln!("\t{}: {}", environment.title, environment.is_main);
-                if environment.is_main {
-                    // eprintln!("{:#?}", environment);
-                    // eprintln!("\t{}", environment.name);
-                    info!(environment.name);
-                    if let Some(head_commit) = environment.head_commit.as_ref() {
-                        let git_commit = client
-                            .git_commit(&subscription.project_id, head_commit)
-                            .await?;
-
-                        let mut service_versions = HashMap::new();
-                        // Check .platform/services.yaml
-                        if let Some(dot_platform) = client
-                            .git_tree_lookup_path(
-                                &subscription.project_id,
-                                &git_commit.tree,
-                                ".platform",
-                            )
-                            .await?
-                        {
-                            if let Some(services_yaml) = client
-                                .git_tree_lookup_path(
-                                    &subscription.project_id,
-                                    &dot_platform.sha,
-                                    "services.yaml",
-                                )
-                                .await?
-                            {
-                                // eprintln!("\t\tgot a services file {}", services_yaml.t_type);
-                                info!(services_yaml.r#type);
-                                if let Ok(buffer) = client
-                                    .git_blob_decode(&subscription.project_id, &services_yaml.sha)
-                                    .await
-                                {
-                                    if let Ok(services) =
-                                        serde_yaml::from_slice::<
-                                            HashMap<String, platform::PlatformService>,
-                                        >(&buffer)
-                                    {
-                                        // eprintln!("{:#?}", services);
-                                        for (name, service) in services.iter() {
-                                            //eprintln!("\t\t\t{}: {}", name, service.s_type);
-                                            info!(name, service.r#type);
-                                            //if service.r#type.starts_with("elasticsearch") {
-                                            //    service_versions.insert("elasticsearch", service.r#type.clone());
-                                            //}
-                                            if let Some((name, version)) =
-                                                service.r#type.split_once(':')
-                                            {
-                                                service_versions
-                                                    .insert(name.to_string(), version.to_string());
-                                                let count = services_cnt
-                                                    .entry(name.to_string())
-                                                    .or_insert(0);
-                                                *count += 1;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-
-                        let items = client
-                            .git_tree_find(
-                                &subscription.project_id,
-                                &git_commit.tree,
-                                |path| {
-                                    path == ".platform.app.yaml"
-                                        || path == "composer.lock"
-                                        || path.ends_with(".make")
-                                },
-                                2,
-                                "".to_string(),
-                            )
-                            .await?;
-
-                        for item in items.iter().filter(|x| x.path == ".platform.app.yaml") {
-                            let blob = client.git_blob(&subscription.project_id, &item.sha).await?;
-                            if let Ok(content) = &general_purpose::STANDARD.decode(blob.content) {
-                                match serde_yaml::from_slice::<platform::PlatformApp>(content) {
-                                    Err(error) => {
-                                        // eprintln!("{:#?}", error);
-                                        error!(%error, "Unreadable yaml file");
-                                        unreadable
-                                            .entry(&subscription.project_id)
-                                            .and_modify(|files| files.push(item.fullpath.clone()))
-                                            .or_insert(vec![item.fullpath.clone()]);
-                                    }
-                                    Ok(app) => {
-                                        // eprintln!("{:#?}", app);
-                                        let _guard =
-                                            span!(tracing::Level::INFO, "app", name = &app.name)
-                                                .entered();
-
-                                        let mut version = HashMap::new();
-                                        if app.r#type.starts_with("php:") {
-                                            for lock in items.iter().filter(|x| {
-                                                x.path == "composer.lock" && x.parent == item.parent
-                                            }) {
-                                                // eprintln!("got {} {}", app.name, lock.path);
-                                                info!(
-                                                    app.name,
-                                                    path = lock.fullpath,
-                                                    "composer.lock"
-                                                );
-
-                                                if let Ok(buffer) = client
-                                                    .git_blob_decode(
-                                                        &subscription.project_id,
-                                                        &lock.sha,
-                                                    )
-                                                    .await
-                                                {
-                                                    if let Ok(composer_lock) =
-                                                        serde_json::from_slice::<
-                                                            php_composer::ComposerLock,
-                                                        >(
-                                                            &buffer
-                                                        )
-                                                    {
-                                                        for package in &composer_lock.packages {
-                                                            if let Some(name) =
-                                                                packages_map.get(&package.name)
-                                                            {
-                                                                version.insert(
-                                                                    name.to_string(),
-                                                                    package.version.to_string(),
-                                                                );
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-
-                                            if let Some(build) = app.build {
-                                                if let Some(flavor) = build.get("flavor") {
-                                                    if flavor == "drupal" {
-                                                        // This shit is oldschool...
-                                                        for make in items.iter().filter(|x| {
-                                                            x.path.ends_with(".make")
-                                                                && x.parent == item.parent
-                                                        }) {
-                                                            if let Ok(buffer) = client
-                                                                .git_blob_decode(
-                                                                    &subscription.project_id,
-                                                                    &make.sha,
-                                                                )
-                                                                .await
-                                                            {
-                                                                if let Ok(content) =
-                                                                    str::from_utf8(&buffer)
-                                                                {
-                                                                    for line in content.lines() {
-                                                                        for cap in drupal
-                                                                            .captures_iter(line)
-                                                                        {
-                                                                            version.insert(
-                                                                                "drupal"
-                                                                                    .to_string(),
-                                                                                cap[1].to_string(),
-                                                                            );
-                                                                        }
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-
-                                        let report = Report {
-                                            subscription: subscription.project_id.to_string(),
-                                            title: subscription.project_title.to_string(),
-                                            plan: subscription.plan.to_string(),
-                                            storage: subscription.storage,
-                                            region: subscription
-                                                .project_region
-                                                .clone()
-                                                .unwrap_or("".to_string()),
-
-                                            last_backup_at: environment.last_backup_at,
-
-                                            app: app.name.to_string(),
-                                            r#type: app.r#type.to_string(),
-                                            packages: version,
-                                            services: service_versions.clone(),
-                                        };
-
-                                        lines.push(report);
-                                    }
-                                }
-                            }
-                        }
-                    } else {
-                        // eprintln!("no head commit");
-                        warn!("no head commit");
-                    }
-                }
-            }
-        } else {
-            let report = Report {
-                subscription: subscription.project_id.to_string(),
-                title: subscription.project_title.to_string(),
-                plan: subscription.plan.to_string(),
-                storage: subscription.storage,
-                region: subscription
-                    .project_region
-                    .clone()
-                    .unwrap_or("".to_string()),
-
-                last_backup_at: None,
-
-                r#type: "".to_string(),
-                app: "".to_string(),
-                packages: HashMap::new(),
-                services: HashMap::new(),
-            };
-            lines.push(report);
+        if !scan.unreadable.is_empty() {
+            unreadable.insert(&subscription.project_id, scan.unreadable);
         }
-        // break;
+        lines.extend(scan.lines);
     }
 
     let mut heading = vec![
@@ -393,9 +378,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut packages_cols = config.report_cols();
     heading.append(&mut packages_cols);
 
-    let mut wtr = csv::Writer::from_writer(io::stdout());
-    wtr.write_record(heading)?;
+    let mut advisory_cols: Vec<String> = Vec::new();
+    for advisory in advisories.iter() {
+        if !advisory_cols.contains(&advisory.id) {
+            advisory_cols.push(advisory.id.clone());
+        }
+    }
+    heading.extend(advisory_cols.iter().cloned());
 
+    let mut rows = Vec::new();
     let report_cols = config.report_cols();
     lines.sort_by_cached_key(|x| -> String { format!("{}-{}", x.title.to_lowercase(), x.app) });
     for line in lines.iter() {
@@ -430,11 +421,42 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 None => "".to_string(),
             })
         }
-        wtr.write_record(&record)?;
+
+        for i in advisory_cols.iter() {
+            record.push(match line.advisories.get(i) {
+                Some(status) => status.to_string(),
+                None => "".to_string(),
+            })
+        }
+        rows.push(record);
     }
 
-    wtr.flush()?;
+    let table = output::Table { heading, rows };
+    output::write(args.format, io::stdout().lock(), &lines, &table)?;
+
+    let mut vulnerable: BTreeMap<&String, Vec<String>> = BTreeMap::new();
+    let mut unknown: BTreeMap<&String, Vec<String>> = BTreeMap::new();
+    for line in lines.iter() {
+        for (advisory, status) in line.advisories.iter() {
+            let found = match status {
+                php_composer::AdvisoryStatus::Vulnerable => &mut vulnerable,
+                php_composer::AdvisoryStatus::Unknown => &mut unknown,
+                _ => continue,
+            };
+            found
+                .entry(&line.subscription)
+                .or_default()
+                .push(format!("{}: {}", line.app, advisory));
+        }
+    }
+    if !unknown.is_empty() {
+        eprintln!("Unknown:\n{:#?}", unknown);
+    }
+    if !vulnerable.is_empty() {
+        eprintln!("Vulnerable:\n{:#?}", vulnerable);
+        return Ok(ExitCode::FAILURE);
+    }
 
     // println!("{:#?}", lines);
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
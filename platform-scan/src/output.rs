@@ -0,0 +1,212 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::{self, Write};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum Format {
+    #[default]
+    Csv,
+    /// One array of all reports, packages and services as nested objects
+    Json,
+    /// One report per line
+    Ndjson,
+    Markdown,
+    /// Standalone page with a table sortable by clicking the headings
+    Html,
+}
+
+/// The reports laid out as columns, for the tabular formats
+pub struct Table {
+    pub heading: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// Write `items` in `format` - serialized as they are for JSON and NDJSON,
+/// as `table` otherwise
+pub fn write<T: Serialize>(
+    format: Format,
+    mut out: impl Write,
+    items: &[T],
+    table: &Table,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        Format::Csv => {
+            let mut wtr = csv::Writer::from_writer(out);
+            wtr.write_record(&table.heading)?;
+            for row in table.rows.iter() {
+                wtr.write_record(row)?;
+            }
+            wtr.flush()?;
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, items)?;
+            writeln!(out)?;
+        }
+        Format::Ndjson => {
+            for item in items {
+                serde_json::to_writer(&mut out, item)?;
+                writeln!(out)?;
+            }
+        }
+        Format::Markdown => markdown(&mut out, table)?,
+        Format::Html => html(&mut out, table)?,
+    }
+    Ok(())
+}
+
+/// A cell's text, with anything that would end the cell or the row escaped,
+/// and HTML too as renderers pass it through
+fn markdown_cell(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('|', "\\|")
+        .replace(['\r', '\n'], " ")
+}
+
+fn markdown(out: &mut impl Write, table: &Table) -> io::Result<()> {
+    let line = |cells: &[String]| {
+        format!(
+            "| {} |",
+            cells
+                .iter()
+                .map(|c| markdown_cell(c))
+                .collect::<Vec<_>>()
+                .join(" | ")
+        )
+    };
+
+    writeln!(out, "{}", line(&table.heading))?;
+    writeln!(out, "|{}", "---|".repeat(table.heading.len()))?;
+    for row in table.rows.iter() {
+        writeln!(out, "{}", line(row))?;
+    }
+    Ok(())
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+const HTML_HEAD: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>platform-scan</title>
+<style>
+body { font-family: sans-serif; font-size: 14px; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; }
+th { background: #eee; cursor: pointer; user-select: none; }
+th[aria-sort=ascending]::after { content: " \25B2"; }
+th[aria-sort=descending]::after { content: " \25BC"; }
+tr:nth-child(even) td { background: #f8f8f8; }
+</style>
+</head>
+<body>
+"#;
+
+/// Sorts on clicking a heading, numbers and versions naturally, empty cells
+/// last
+const HTML_SCRIPT: &str = r#"<script>
+document.querySelectorAll("table").forEach(function (table) {
+  var collator = new Intl.Collator(undefined, { numeric: true, sensitivity: "base" });
+  table.querySelectorAll("th").forEach(function (th, column) {
+    th.addEventListener("click", function () {
+      var ascending = th.getAttribute("aria-sort") !== "ascending";
+      table.querySelectorAll("th").forEach(function (other) { other.removeAttribute("aria-sort"); });
+      th.setAttribute("aria-sort", ascending ? "ascending" : "descending");
+      var tbody = table.tBodies[0];
+      Array.from(tbody.rows).sort(function (a, b) {
+        var x = a.cells[column].textContent, y = b.cells[column].textContent;
+        if (x === "" || y === "") return (x === "") - (y === "");
+        return ascending ? collator.compare(x, y) : collator.compare(y, x);
+      }).forEach(function (row) { tbody.appendChild(row); });
+    });
+  });
+});
+</script>
+</body>
+</html>
+"#;
+
+fn html(out: &mut impl Write, table: &Table) -> io::Result<()> {
+    write!(out, "{}", HTML_HEAD)?;
+    writeln!(out, "<table>")?;
+    writeln!(out, "<thead>")?;
+    writeln!(
+        out,
+        "<tr>{}</tr>",
+        table
+            .heading
+            .iter()
+            .map(|h| format!("<th>{}</th>", escape(h)))
+            .collect::<String>()
+    )?;
+    writeln!(out, "</thead>")?;
+    writeln!(out, "<tbody>")?;
+    for row in table.rows.iter() {
+        writeln!(
+            out,
+            "<tr>{}</tr>",
+            row.iter()
+                .map(|c| format!("<td>{}</td>", escape(c)))
+                .collect::<String>()
+        )?;
+    }
+    writeln!(out, "</tbody>")?;
+    writeln!(out, "</table>")?;
+    write!(out, "{}", HTML_SCRIPT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Table {
+        Table {
+            heading: vec!["Title".to_string(), "App".to_string()],
+            rows: vec![
+                vec!["A | B".to_string(), "ends in \\".to_string()],
+                vec![
+                    "<script>\"x\" & 'y'</script>".to_string(),
+                    "two\nlines".to_string(),
+                ],
+            ],
+        }
+    }
+
+    #[test]
+    fn escapes_markdown_cells() {
+        let mut out = Vec::new();
+        markdown(&mut out, &table()).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "| Title | App |\n\
+             |---|---|\n\
+             | A \\| B | ends in \\\\ |\n\
+             | &lt;script&gt;\"x\" &amp; 'y'&lt;/script&gt; | two lines |\n"
+        );
+    }
+
+    #[test]
+    fn escapes_html_cells() {
+        let mut out = Vec::new();
+        html(&mut out, &table()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains("<tr><th>Title</th><th>App</th></tr>"));
+        assert!(
+            out.contains("<td>&lt;script&gt;&quot;x&quot; &amp; &#39;y&#39;&lt;/script&gt;</td>")
+        );
+        assert!(!out.contains("<script>\""));
+    }
+}
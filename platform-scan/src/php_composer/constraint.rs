@@ -0,0 +1,466 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// Stability of a version, from least to most stable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Stability {
+    Dev,
+    Alpha,
+    Beta,
+    Rc,
+    Stable,
+    Patch,
+}
+
+/// A package version as Composer compares them, e.g. `2.4.0`, `v1.2`,
+/// `3.0.0-beta2` or Drupal's `8.x-1.5`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    parts: [u64; 4],
+    stability: Stability,
+    /// The 2 of `beta2`
+    number: u64,
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.parts, self.stability, self.number).cmp(&(other.parts, other.stability, other.number))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid version constraint: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A version as written, before padding: the given numeric parts and the
+/// stability suffix, if any
+struct Partial {
+    parts: Vec<u64>,
+    suffix: Option<(Stability, u64)>,
+}
+
+impl Partial {
+    fn parse(value: &str) -> Option<Partial> {
+        let value = value.trim();
+        let value = value
+            .split_once('+')
+            .map_or(value, |(version, _build)| version);
+        let value = value.trim_start_matches(['v', 'V']);
+        // Drupal contrib versions, 8.x-1.5 is 1.5
+        let value = match value.split_once(".x-") {
+            Some((core, rest)) if core.chars().all(|c| c.is_ascii_digit()) => rest,
+            _ => value,
+        };
+
+        let end = value
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(value.len());
+        let (numeric, suffix) = value.split_at(end);
+        let parts = numeric
+            .trim_end_matches('.')
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        if parts.len() > 4 {
+            return None;
+        }
+
+        let suffix = suffix.trim_start_matches(['-', '_', '.']).to_lowercase();
+        let suffix = if suffix.is_empty() {
+            None
+        } else {
+            let split = suffix
+                .find(|c: char| c.is_ascii_digit() || c == '.')
+                .unwrap_or(suffix.len());
+            let (name, number) = suffix.split_at(split);
+            let stability = match name {
+                "dev" => Stability::Dev,
+                "alpha" | "a" => Stability::Alpha,
+                "beta" | "b" => Stability::Beta,
+                "rc" => Stability::Rc,
+                "stable" => Stability::Stable,
+                "patch" | "pl" | "p" => Stability::Patch,
+                _ => return None,
+            };
+            let number = number.trim_start_matches('.');
+            let number = if number.is_empty() {
+                0
+            } else {
+                number.parse().ok()?
+            };
+            Some((stability, number))
+        };
+
+        Some(Partial { parts, suffix })
+    }
+
+    /// Padded with zeros, `default` stability unless one was given
+    fn version(&self, default: Stability) -> Version {
+        let mut parts = [0; 4];
+        parts[..self.parts.len()].copy_from_slice(&self.parts);
+        let (stability, number) = self.suffix.unwrap_or((default, 0));
+        Version {
+            parts,
+            stability,
+            number,
+        }
+    }
+
+    /// The lowest dev version after every version starting with the first
+    /// `len` parts, 1.3 for 1.2.* or ~1.2.0
+    fn bump(&self, len: usize) -> Version {
+        let mut parts = [0; 4];
+        parts[..len].copy_from_slice(&self.parts[..len]);
+        parts[len - 1] += 1;
+        Version {
+            parts,
+            stability: Stability::Dev,
+            number: 0,
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = ParseError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Partial::parse(value)
+            .map(|partial| partial.version(Stability::Stable))
+            .ok_or_else(|| ParseError(value.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Bound {
+    op: Op,
+    version: Version,
+}
+
+impl Bound {
+    fn new(op: Op, partial: &Partial) -> Bound {
+        // As in Composer, >=1.2 includes 1.2.0-beta and <2.0 excludes
+        // 2.0.0-beta
+        let default = match op {
+            Op::Ge | Op::Lt => Stability::Dev,
+            _ => Stability::Stable,
+        };
+        Bound {
+            op,
+            version: partial.version(default),
+        }
+    }
+
+    /// Every version this bound allows is below `version`
+    fn is_below(&self, version: &Version) -> bool {
+        match self.op {
+            Op::Lt => self.version <= *version,
+            Op::Le | Op::Eq => self.version < *version,
+            Op::Ne | Op::Gt | Op::Ge => false,
+        }
+    }
+
+    fn matches(&self, version: &Version) -> bool {
+        let ordering = version.cmp(&self.version);
+        match self.op {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// A Composer version constraint, e.g. `>=2.0 <2.4.1 || ^3.0`
+///
+/// Supports `||` (or `|`) between alternatives, `,` or a space between
+/// conditions that all have to hold, the `=`, `==`, `!=`, `<`, `<=`, `>`,
+/// `>=`, `^` and `~` operators, wildcards like `1.2.*` and hyphen ranges like
+/// `1.0 - 2.0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    /// Any of these, each a list of bounds that all have to match. An empty
+    /// list matches anything.
+    any_of: Vec<Vec<Bound>>,
+}
+
+impl Constraint {
+    pub fn matches(&self, version: &Version) -> bool {
+        self.any_of
+            .iter()
+            .any(|all| all.iter().all(|bound| bound.matches(version)))
+    }
+
+    /// `version` doesn't match, and is past every version one of the
+    /// alternatives allows, even if another one allows later versions, e.g.
+    /// 2.5 is past `>=2.0 <2.4.1 || ^3.0` but 1.9 isn't past `>=2.0 <2.4.1`
+    pub fn is_below(&self, version: &Version) -> bool {
+        !self.matches(version)
+            && self
+                .any_of
+                .iter()
+                .any(|all| all.iter().any(|bound| bound.is_below(version)))
+    }
+
+    /// Bounds of a single condition, e.g. `^1.2` or `<2.0`
+    fn condition(condition: &str) -> Option<Vec<Bound>> {
+        if matches!(condition, "*" | "x" | "X") {
+            return Some(Vec::new());
+        }
+
+        let operators = [
+            (">=", Op::Ge),
+            ("<=", Op::Le),
+            ("!=", Op::Ne),
+            ("<>", Op::Ne),
+            ("==", Op::Eq),
+            (">", Op::Gt),
+            ("<", Op::Lt),
+            ("=", Op::Eq),
+        ];
+        for (prefix, op) in operators {
+            if let Some(version) = condition.strip_prefix(prefix) {
+                return Some(vec![Bound::new(op, &Partial::parse(version)?)]);
+            }
+        }
+
+        if let Some(version) = condition.strip_prefix('^') {
+            let partial = Partial::parse(version)?;
+            // Up to the first non-zero part, ^0.3 is <0.4 and ^1.2 is <2.0
+            let len = partial
+                .parts
+                .iter()
+                .position(|part| *part != 0)
+                .map_or(partial.parts.len(), |i| i + 1);
+            return Some(vec![
+                Bound::new(Op::Ge, &partial),
+                Bound {
+                    op: Op::Lt,
+                    version: partial.bump(len),
+                },
+            ]);
+        }
+
+        if let Some(version) = condition.strip_prefix('~') {
+            let partial = Partial::parse(version)?;
+            // The last given part may change, ~1.2 is <2.0 and ~1.2.3 is <1.3
+            let len = partial.parts.len().saturating_sub(1).max(1);
+            return Some(vec![
+                Bound::new(Op::Ge, &partial),
+                Bound {
+                    op: Op::Lt,
+                    version: partial.bump(len),
+                },
+            ]);
+        }
+
+        if let Some(version) = condition
+            .strip_suffix(".*")
+            .or_else(|| condition.strip_suffix(".x"))
+            .or_else(|| condition.strip_suffix(".X"))
+        {
+            let partial = Partial::parse(version)?;
+            return Some(vec![
+                Bound::new(Op::Ge, &partial),
+                Bound {
+                    op: Op::Lt,
+                    version: partial.bump(partial.parts.len()),
+                },
+            ]);
+        }
+
+        Some(vec![Bound::new(Op::Eq, &Partial::parse(condition)?)])
+    }
+
+    /// `from - to`, a partial `to` includes everything it starts with
+    fn range(from: &str, to: &str) -> Option<Vec<Bound>> {
+        let from = Partial::parse(from)?;
+        let to = Partial::parse(to)?;
+        let upper = if to.parts.len() < 3 && to.suffix.is_none() {
+            Bound {
+                op: Op::Lt,
+                version: to.bump(to.parts.len()),
+            }
+        } else {
+            Bound::new(Op::Le, &to)
+        };
+        Some(vec![Bound::new(Op::Ge, &from), upper])
+    }
+
+    fn all_of(conditions: &str) -> Option<Vec<Bound>> {
+        // Operators may be separated from their version by spaces
+        let mut tokens: Vec<String> = Vec::new();
+        let mut operator = String::new();
+        for token in conditions.replace(',', " ").split_whitespace() {
+            if token != "-" && token.chars().all(|c| "<>=!^~".contains(c)) {
+                operator.push_str(token);
+            } else {
+                tokens.push(format!("{}{}", operator, token));
+                operator.clear();
+            }
+        }
+        if !operator.is_empty() {
+            return None;
+        }
+
+        let mut bounds = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            if tokens.get(i + 1).is_some_and(|token| token == "-") {
+                bounds.extend(Constraint::range(&tokens[i], tokens.get(i + 2)?)?);
+                i += 3;
+            } else {
+                bounds.extend(Constraint::condition(&tokens[i])?);
+                i += 1;
+            }
+        }
+        Some(bounds)
+    }
+}
+
+impl FromStr for Constraint {
+    type Err = ParseError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let any_of = value
+            .split('|')
+            .filter(|alternative| !alternative.trim().is_empty())
+            .map(Constraint::all_of)
+            .collect::<Option<Vec<_>>>()
+            .filter(|any_of| !any_of.is_empty())
+            .ok_or_else(|| ParseError(value.to_string()))?;
+        Ok(Constraint { any_of })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(constraint: &str, version: &str) -> bool {
+        constraint
+            .parse::<Constraint>()
+            .unwrap()
+            .matches(&version.parse().unwrap())
+    }
+
+    #[test]
+    fn compares_versions() {
+        let versions = [
+            "1.0.0-dev",
+            "1.0.0-alpha1",
+            "1.0.0-beta1",
+            "1.0.0-beta2",
+            "1.0.0-RC1",
+            "1.0.0",
+            "1.0.0.1",
+            "1.0.1",
+            "8.x-1.5",
+            "v2.0",
+        ];
+        for pair in versions.windows(2) {
+            let a: Version = pair[0].parse().unwrap();
+            let b: Version = pair[1].parse().unwrap();
+            assert!(a < b, "{} < {}", pair[0], pair[1]);
+        }
+        assert_eq!(
+            "1.2".parse::<Version>().unwrap(),
+            "1.2.0.0".parse().unwrap()
+        );
+        assert!("dev-master".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn matches_constraints() {
+        let cases = [
+            ("<2.5", "2.4.9", true),
+            ("<2.5", "2.5.0-beta1", false),
+            ("<2.5", "2.5.0", false),
+            (">=2.0 <2.4.1", "2.4.0", true),
+            (">=2.0, <2.4.1", "2.4.1", false),
+            (">= 2.0 < 2.4.1", "1.9.9", false),
+            ("^1.2.3", "1.9.0", true),
+            ("^1.2.3", "2.0.0", false),
+            ("^0.3", "0.3.9", true),
+            ("^0.3", "0.4.0", false),
+            ("~1.2", "1.9.0", true),
+            ("~1.2", "2.0.0", false),
+            ("~1.2.3", "1.2.9", true),
+            ("~1.2.3", "1.3.0", false),
+            ("1.2.*", "1.2.7", true),
+            ("1.2.*", "1.3.0", false),
+            ("1.0 - 2.0", "2.0.5", true),
+            ("1.0 - 2.0", "2.1.0", false),
+            ("1.0.0 - 2.1.0", "2.1.1", false),
+            ("1.4.2", "1.4.2", true),
+            ("!=1.4.2", "1.4.2", false),
+            ("<1.5 || >=2.0 <2.3.1", "2.3.0", true),
+            ("<1.5 | >=2.0 <2.3.1", "1.8.0", false),
+            ("*", "3.0.0", true),
+            ("<2.0", "8.x-1.5", true),
+        ];
+        for (constraint, version, expected) in cases {
+            assert_eq!(
+                matches(constraint, version),
+                expected,
+                "{} {}",
+                constraint,
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn finds_versions_past_constraints() {
+        let cases = [
+            (">=2.0 <2.4.1", "2.4.1", true),
+            (">=2.0 <2.4.1", "1.9.0", false),
+            (">=2.0 <2.4.1", "2.4.0", false),
+            (">=2.0 <2.4.1 || ^3.0", "2.5.0", true),
+            ("<1.5 || ~3.1", "4.0.0", true),
+            ("1.4.2", "1.4.3", true),
+            (">=3.0", "9.0.0", false),
+            ("*", "1.0.0", false),
+        ];
+        for (constraint, version, expected) in cases {
+            let constraint: Constraint = constraint.parse().unwrap();
+            assert_eq!(
+                constraint.is_below(&version.parse().unwrap()),
+                expected,
+                "{:?} {}",
+                constraint,
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_constraints() {
+        for constraint in ["", "||", ">=", "^foo", "1.0 -", "1.0.0.0.0"] {
+            assert!(constraint.parse::<Constraint>().is_err(), "{}", constraint);
+        }
+    }
+}
@@ -1,7 +1,11 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
 use serde::{Deserialize, Serialize};
 
+mod constraint;
+
+pub use constraint::{Constraint, ParseError, Version};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ComposerLockPackage {
     pub name: String,
@@ -21,3 +25,54 @@ pub struct ComposerLock {
 }
 
 impl ComposerLock {}
+
+/// A security advisory for the versions of a package matching `affected`
+#[derive(Debug, Clone)]
+pub struct Advisory {
+    /// E.g. SA-CONTRIB-2024-006 or CVE-2023-30777
+    pub id: String,
+    pub package: String,
+    pub affected: Constraint,
+}
+
+/// Ordered from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AdvisoryStatus {
+    /// The package isn't installed, or in a version from before the affected
+    /// ones
+    Unaffected,
+    /// The package is installed, in a version after the affected ones
+    Fixed,
+    /// The installed version can't be compared, e.g. dev-main, or the
+    /// composer.lock couldn't be read
+    Unknown,
+    /// The installed version is affected
+    Vulnerable,
+}
+
+impl Advisory {
+    /// Status of an app with `installed` version of the package, if any
+    pub fn status(&self, installed: Option<&str>) -> AdvisoryStatus {
+        let Some(installed) = installed else {
+            return AdvisoryStatus::Unaffected;
+        };
+        match installed.parse::<Version>() {
+            Ok(version) if self.affected.matches(&version) => AdvisoryStatus::Vulnerable,
+            Ok(version) if self.affected.is_below(&version) => AdvisoryStatus::Fixed,
+            Ok(_) => AdvisoryStatus::Unaffected,
+            Err(_) => AdvisoryStatus::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for AdvisoryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AdvisoryStatus::Unaffected => "unaffected",
+            AdvisoryStatus::Fixed => "fixed",
+            AdvisoryStatus::Unknown => "unknown",
+            AdvisoryStatus::Vulnerable => "vulnerable",
+        })
+    }
+}
@@ -0,0 +1,375 @@
+use futures::future::{join_all, try_join_all};
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, HashMap},
+    str,
+};
+use tokio::sync::Semaphore;
+use tracing::{info, span, warn, Instrument};
+
+use crate::{php_composer, Report, RouteReport};
+
+/// Everything found in a single subscription
+#[derive(Debug, Default)]
+pub struct SubscriptionScan {
+    pub lines: Vec<Report>,
+    /// Name of every versioned service in services.yaml, e.g. "mariadb"
+    pub services: Vec<String>,
+    /// Configuration files, or entries in them, that couldn't be parsed
+    pub unreadable: Vec<String>,
+    /// Only with [`Scanner::with_routes`]
+    pub routes: Vec<RouteReport>,
+}
+
+pub struct Scanner {
+    client: platform::ApiClient,
+    packages_map: HashMap<String, String>,
+    drupal: Regex,
+    subscriptions: Semaphore,
+    concurrency: usize,
+    routes: bool,
+    advisories: Vec<php_composer::Advisory>,
+}
+
+impl Scanner {
+    /// Scan up to `concurrency` subscriptions at a time, and within each of
+    /// them download up to `concurrency` blobs at a time.
+    pub fn new(
+        client: platform::ApiClient,
+        packages_map: HashMap<String, String>,
+        concurrency: usize,
+    ) -> Scanner {
+        Scanner {
+            client,
+            packages_map,
+            drupal: Regex::new(r"projects\[drupal\]\[version\]\s*=\s*([0-9.]+)").unwrap(),
+            subscriptions: Semaphore::new(concurrency),
+            concurrency,
+            routes: false,
+            advisories: Vec::new(),
+        }
+    }
+
+    /// Also make an inventory of the routes, resolved against the domains of
+    /// each project
+    pub fn with_routes(mut self, routes: bool) -> Scanner {
+        self.routes = routes;
+        self
+    }
+
+    /// Check the composer.lock of every app against these advisories
+    pub fn with_advisories(mut self, advisories: Vec<php_composer::Advisory>) -> Scanner {
+        self.advisories = advisories;
+        self
+    }
+
+    /// Scan all subscriptions, results are in the same order as `subscriptions`
+    pub async fn scan<'a>(
+        &self,
+        subscriptions: impl IntoIterator<Item = &'a platform::Subscription>,
+    ) -> Result<Vec<SubscriptionScan>, platform::Error> {
+        try_join_all(subscriptions.into_iter().map(|subscription| async move {
+            let _permit = self.subscriptions.acquire().await.unwrap();
+            let span = span!(
+                tracing::Level::INFO,
+                "subscription",
+                id = &subscription.project_id
+            );
+            self.scan_subscription(subscription).instrument(span).await
+        }))
+        .await
+    }
+
+    /// Download a blob once one of the subscription's `blobs` permits is free
+    async fn blob(
+        &self,
+        blobs: &Semaphore,
+        project_id: &str,
+        sha: &str,
+    ) -> Result<Vec<u8>, platform::Error> {
+        let _permit = blobs.acquire().await.unwrap();
+        self.client.git_blob_decode(project_id, sha).await
+    }
+
+    async fn scan_subscription(
+        &self,
+        subscription: &platform::Subscription,
+    ) -> Result<SubscriptionScan, platform::Error> {
+        let client = &self.client;
+        let mut scan = SubscriptionScan::default();
+        let blobs = Semaphore::new(self.concurrency);
+
+        info!(
+            subscription.project_id,
+            subscription.project_title, subscription.plan, subscription.storage
+        );
+
+        // A project that can't be read (no access, unexpected response, ...)
+        // is reported as an empty line, only the API being down or rejecting
+        // our token aborts the scan
+        let environments: Vec<platform::Environment> = match client
+            .get_json(format!("projects/{}/environments", subscription.project_id))
+            .await
+        {
+            Err(error) if is_fatal(&error) => return Err(error),
+            Err(error) => {
+                warn!(%error, "unreadable environments");
+                scan.lines.push(Report::new(subscription, None));
+                return Ok(scan);
+            }
+            Ok(environments) => environments,
+        };
+
+        for environment in environments.iter().filter(|x| x.is_main) {
+            info!(environment.name);
+            let Some(head_commit) = environment.head_commit.as_ref() else {
+                warn!("no head commit");
+                continue;
+            };
+
+            let commit_config = async {
+                let git_commit = client
+                    .git_commit(&subscription.project_id, head_commit)
+                    .await?;
+                let config = client
+                    .project_config(&subscription.project_id, &git_commit.tree)
+                    .await?;
+                Ok::<_, platform::Error>((git_commit, config))
+            };
+            let (git_commit, config) = match commit_config.await {
+                Err(error) if is_fatal(&error) => return Err(error),
+                Err(error) => {
+                    warn!(%error, "unreadable head commit");
+                    scan.lines
+                        .push(Report::new(subscription, Some(environment)));
+                    continue;
+                }
+                Ok(found) => found,
+            };
+            scan.unreadable.extend(config.unreadable.iter().cloned());
+
+            if self.routes {
+                self.scan_routes(subscription, environment, &config, &mut scan)
+                    .await?;
+            }
+
+            let mut service_versions = BTreeMap::new();
+            for (name, service) in config.services.iter() {
+                info!(name, service.r#type);
+                if let Some((name, version)) = service.r#type.split_once(':') {
+                    service_versions.insert(name.to_string(), version.to_string());
+                    scan.services.push(name.to_string());
+                }
+            }
+
+            let apps = join_all(config.apps.iter().map(|app| {
+                self.scan_app(
+                    &blobs,
+                    subscription,
+                    environment,
+                    &git_commit.tree,
+                    app,
+                    &service_versions,
+                )
+            }))
+            .await;
+
+            for app in apps {
+                let (report, unreadable) = app?;
+                scan.lines.push(report);
+                scan.unreadable.extend(unreadable);
+            }
+        }
+
+        Ok(scan)
+    }
+
+    async fn scan_routes(
+        &self,
+        subscription: &platform::Subscription,
+        environment: &platform::Environment,
+        config: &platform::ProjectConfig,
+        scan: &mut SubscriptionScan,
+    ) -> Result<(), platform::Error> {
+        let domains = match self.client.domains(&subscription.project_id).await {
+            Err(error) if error.is_forbidden() || error.is_not_found() => {
+                warn!(%error, "no access to domains");
+                Vec::new()
+            }
+            Err(error) => return Err(error),
+            Ok(domains) => domains,
+        };
+        // Without any domains the environment is only reachable on its
+        // generated hostname
+        let default = domains
+            .iter()
+            .find(|domain| domain.is_default)
+            .or(domains.first())
+            .map_or(environment.edge_hostname.as_str(), |domain| &domain.name);
+        let all: Vec<String> = domains.iter().map(|domain| domain.name.clone()).collect();
+
+        for route in config.resolve_routes(default, &all) {
+            scan.routes.push(RouteReport {
+                subscription: subscription.project_id.clone(),
+                title: subscription.project_title.clone(),
+                host: route.host().to_string(),
+                url: route.url.clone(),
+                r#type: route.route.r#type.clone(),
+                app: route.route.app().unwrap_or_default().to_string(),
+                to: route.to.clone().unwrap_or_default(),
+                primary: route.route.primary.unwrap_or(false),
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn scan_app(
+        &self,
+        blobs: &Semaphore,
+        subscription: &platform::Subscription,
+        environment: &platform::Environment,
+        tree: &str,
+        configured: &platform::ConfiguredApp,
+        service_versions: &BTreeMap<String, String>,
+    ) -> Result<(Report, Option<String>), platform::Error> {
+        let client = &self.client;
+        let app = &configured.app;
+
+        // An app root or composer.lock that couldn't be fetched or parsed,
+        // whatever the app has installed is unknown
+        let mut unreadable = None;
+
+        // composer.lock and drush make files are next to the app's code
+        let root = async {
+            if configured.root.is_empty() {
+                return Ok(client.git_tree(&subscription.project_id, tree).await?.tree);
+            }
+            match client
+                .git_tree_lookup(&subscription.project_id, tree, &configured.root)
+                .await?
+            {
+                Some(item) if item.r#type == "tree" => Ok(client
+                    .git_tree(&subscription.project_id, &item.sha)
+                    .await?
+                    .tree),
+                _ => {
+                    warn!(app.name, configured.root, "app root not found");
+                    Ok(Vec::new())
+                }
+            }
+        };
+        let root = match root.await {
+            Err(error) if is_fatal(&error) => return Err(error),
+            Err(error) => {
+                let path = match configured.root.trim_matches('/') {
+                    "" => ".".to_string(),
+                    root => root.to_string(),
+                };
+                warn!(%error, app.name, path, "Unreadable app root");
+                unreadable = Some(path);
+                Vec::new()
+            }
+            Ok(root) => root,
+        };
+
+        let span = span!(tracing::Level::INFO, "app", name = &app.name);
+        let mut version = BTreeMap::new();
+        // Installed version of each package with an advisory
+        let mut installed: HashMap<&str, String> = HashMap::new();
+        if app.r#type.starts_with("php:") {
+            let project_id = &subscription.project_id;
+            let lock = root.iter().find(|x| x.path == "composer.lock");
+            // This shit is oldschool...
+            let is_drupal_make = app
+                .build
+                .as_ref()
+                .and_then(|build| build.get("flavor"))
+                .is_some_and(|flavor| flavor == "drupal");
+            let makes = root
+                .iter()
+                .filter(|x| is_drupal_make && x.path.ends_with(".make"));
+
+            let (lock_blob, make_blobs) = futures::join!(
+                async {
+                    match lock {
+                        Some(lock) => Some(self.blob(blobs, project_id, &lock.sha).await),
+                        None => None,
+                    }
+                },
+                join_all(makes.map(|make| self.blob(blobs, project_id, &make.sha))),
+            );
+
+            if let Some(lock_blob) = lock_blob {
+                info!(parent: &span, app.name, root = configured.root, "composer.lock");
+
+                let path = format!("{}/composer.lock", configured.root)
+                    .trim_start_matches('/')
+                    .to_string();
+                let composer_lock = match lock_blob {
+                    Err(error) if is_fatal(&error) => return Err(error),
+                    Err(error) => Err(error.to_string()),
+                    Ok(buffer) => serde_json::from_slice::<php_composer::ComposerLock>(&buffer)
+                        .map_err(|error| error.to_string()),
+                };
+                match composer_lock {
+                    Err(error) => {
+                        warn!(parent: &span, error, path, "Unreadable composer.lock");
+                        unreadable = Some(path);
+                    }
+                    Ok(composer_lock) => {
+                        for package in &composer_lock.packages {
+                            if let Some(name) = self.packages_map.get(&package.name) {
+                                version.insert(name.to_string(), package.version.to_string());
+                            }
+                            if let Some(advisory) = self
+                                .advisories
+                                .iter()
+                                .find(|advisory| advisory.package == package.name)
+                            {
+                                installed.insert(&advisory.package, package.version.clone());
+                            }
+                        }
+                    }
+                }
+            }
+
+            for buffer in make_blobs.into_iter().flatten() {
+                if let Ok(content) = str::from_utf8(&buffer) {
+                    for line in content.lines() {
+                        for cap in self.drupal.captures_iter(line) {
+                            version.insert("drupal".to_string(), cap[1].to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut report = Report::new(subscription, Some(environment));
+        report.app = app.name.to_string();
+        report.r#type = app.r#type.to_string();
+        report.packages = version;
+        report.services = service_versions.clone();
+        for advisory in self.advisories.iter() {
+            let status = match installed.get(advisory.package.as_str()) {
+                Some(version) => advisory.status(Some(version)),
+                None if unreadable.is_some() => php_composer::AdvisoryStatus::Unknown,
+                None => advisory.status(None),
+            };
+            // The worst of the packages an advisory covers
+            let entry = report
+                .advisories
+                .entry(advisory.id.clone())
+                .or_insert(status);
+            *entry = (*entry).max(status);
+        }
+
+        Ok((report, unreadable))
+    }
+}
+
+/// Whether `error` aborts the scan: the API is down, or no longer accepts our
+/// token. Anything else only makes a project or an app unreadable.
+fn is_fatal(error: &platform::Error) -> bool {
+    error.is_unavailable() || matches!(error, platform::Error::Auth { .. })
+}
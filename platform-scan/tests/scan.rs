@@ -0,0 +1,559 @@
+use platform_mock::{MockServer, Seed};
+use std::{fs, path::Path, process::Output};
+use tokio::process::Command;
+
+const SEED: &str = r#"
+page_size: 1
+organizations:
+  - id: org-adapt
+    name: adapt
+    subscriptions:
+      - { project_id: drupalsite123, title: Drupal Site, plan: medium, storage: 10240 }
+      - { project_id: brokenyaml123, title: Broken Yaml }
+      - { project_id: forbidden1234, title: Forbidden }
+projects:
+  drupalsite123:
+    environments:
+      - { name: main, is_main: true }
+    files:
+      .platform.app.yaml: |
+        name: drupal
+        type: php:8.2
+        build:
+          flavor: composer
+      composer.lock: |
+        {"packages": [
+          {"name": "drupal/core", "version": "10.2.5", "type": "drupal-core"},
+          {"name": "drupal/swiftmailer", "version": "2.4.0", "type": "drupal-module"}
+        ]}
+      .platform/services.yaml: |
+        db:
+          type: mariadb:10.6
+        cache:
+          type: redis:7.0
+  brokenyaml123:
+    environments:
+      - { name: main, is_main: true }
+    files:
+      .platform.app.yaml: "name: [unterminated\n"
+faults:
+  - { path: /projects/forbidden1234/environments, status: 403, message: Forbidden }
+  - { path: /organizations/org-adapt/subscriptions, status: 429, retry_after: 0, times: 1 }
+  - { path: /projects/drupalsite123/environments, status: 401, times: 1 }
+"#;
+
+const CONFIG: &str = r#"
+frameworks:
+  drupal:
+    - drupal/core
+packages:
+  - drupal/swiftmailer
+"#;
+
+/// Run platform-scan in `dir`, against `server`
+async fn run_scan(dir: &Path, server: &MockServer, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_platform-scan"))
+        .arg("--no-cache")
+        .args(args)
+        .current_dir(dir)
+        .env("PLATFORMSH_API_URL", server.url())
+        .env("PLATFORMSH_AUTH_URL", server.url())
+        .env("PLATFORMSH_CLI_TOKEN", "api-token")
+        .env("TZ", "UTC")
+        .output()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn scans_subscriptions() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("config.yaml"), CONFIG).unwrap();
+
+    let output = run_scan(dir.path(), &server, &["--services"]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{}", stderr);
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "Subscription,Title,Plan,Storage,Region,Last Backup at,Type,App,mariadb,redis,drupal,drupal/swiftmailer"
+    );
+    assert_eq!(
+        &lines[1..],
+        [
+            "drupalsite123,Drupal Site,medium,10240,eu-3.platform.sh,2024-01-01T12:00:00+00:00,php:8.2,drupal,10.6,7.0,10.2.5,2.4.0",
+            "forbidden1234,Forbidden,standard,5120,eu-3.platform.sh,,,,,,,",
+        ]
+    );
+    assert!(stderr.contains("brokenyaml123"), "{}", stderr);
+    assert!(stderr.contains(".platform.app.yaml"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn fails_when_api_is_down() {
+    let mut seed = Seed::from_yaml(SEED).unwrap();
+    seed.faults = vec![platform_mock::Fault {
+        path: "/projects/drupalsite123/environments".to_string(),
+        status: 500,
+        ..Default::default()
+    }];
+    let server = MockServer::start(seed).await;
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("config.yaml"), CONFIG).unwrap();
+
+    let output = run_scan(dir.path(), &server, &[]).await;
+    assert!(!output.status.success());
+}
+
+#[tokio::test]
+async fn finds_apps_in_upsun_config() {
+    let seed = r#"
+organizations:
+  - id: org-adapt
+    name: adapt
+    subscriptions:
+      - { project_id: upsunsite123, title: Upsun Site }
+projects:
+  upsunsite123:
+    environments:
+      - { name: main, is_main: true }
+    files:
+      .upsun/config.yaml: |
+        applications:
+          cms:
+            type: php:8.3
+            source:
+              root: cms
+          frontend:
+            type: nodejs:20
+            source:
+              root: frontend
+        services:
+          db:
+            type: mariadb:11.4
+      cms/composer.lock: |
+        {"packages": [{"name": "drupal/core", "version": "11.0.1", "type": "drupal-core"}]}
+      composer.lock: |
+        {"packages": [{"name": "drupal/core", "version": "9.0.0", "type": "drupal-core"}]}
+"#;
+    let server = MockServer::start(Seed::from_yaml(seed).unwrap()).await;
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("config.yaml"), CONFIG).unwrap();
+
+    let output = run_scan(dir.path(), &server, &["--services"]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "Subscription,Title,Plan,Storage,Region,Last Backup at,Type,App,mariadb,drupal,drupal/swiftmailer",
+            "upsunsite123,Upsun Site,standard,5120,eu-3.platform.sh,2024-01-01T12:00:00+00:00,php:8.3,cms,11.4,11.0.1,",
+            "upsunsite123,Upsun Site,standard,5120,eu-3.platform.sh,2024-01-01T12:00:00+00:00,nodejs:20,frontend,11.4,,",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn lists_routes() {
+    let seed = r#"
+organizations:
+  - id: org-adapt
+    name: adapt
+    subscriptions:
+      - { project_id: shop12345678, title: Shop }
+      - { project_id: intranet1234, title: Intranet }
+projects:
+  shop12345678:
+    default_domain: www.shop.example
+    domains: [shop.example, www.shop.example, shop.example.org]
+    environments:
+      - { name: main, is_main: true }
+    files:
+      .platform.app.yaml: "name: app\ntype: php:8.3\n"
+      .platform/routes.yaml: |
+        "https://{all}/":
+          type: upstream
+          upstream: "app:http"
+          primary: true
+          cache:
+            enabled: true
+            cookies: ["SESS*"]
+        "https://api.{default}/":
+          type: upstream
+          upstream: "api:http"
+          ssi:
+            enabled: false
+        "http://{all}/":
+          type: redirect
+          to: "https://{all}/"
+  intranet1234:
+    environments:
+      - { name: main, is_main: true }
+    files:
+      .upsun/config.yaml: |
+        applications:
+          web:
+            type: nodejs:20
+        routes:
+          "https://{default}/":
+            type: upstream
+            upstream: web:http
+            id: main
+"#;
+    let server = MockServer::start(Seed::from_yaml(seed).unwrap()).await;
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("config.yaml"), CONFIG).unwrap();
+
+    let output = run_scan(dir.path(), &server, &["--routes"]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "Subscription,Title,Host,URL,Type,App,Redirect to,Primary",
+            "intranet1234,Intranet,main-abc123-intranet1234.eu-3.platformsh.site,https://main-abc123-intranet1234.eu-3.platformsh.site/,upstream,web,,false",
+            "shop12345678,Shop,shop.example.org,http://shop.example.org/,redirect,,https://shop.example.org/,false",
+            "shop12345678,Shop,shop.example,http://shop.example/,redirect,,https://shop.example/,false",
+            "shop12345678,Shop,www.shop.example,http://www.shop.example/,redirect,,https://www.shop.example/,false",
+            "shop12345678,Shop,api.www.shop.example,https://api.www.shop.example/,upstream,api,,false",
+            "shop12345678,Shop,shop.example.org,https://shop.example.org/,upstream,app,,true",
+            "shop12345678,Shop,shop.example,https://shop.example/,upstream,app,,true",
+            "shop12345678,Shop,www.shop.example,https://www.shop.example/,upstream,app,,true",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn writes_other_formats() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("config.yaml"), CONFIG).unwrap();
+
+    let scan = |format: &'static str| {
+        let (dir, server) = (&dir, &server);
+        async move {
+            let output = run_scan(dir.path(), server, &["--services", "--format", format]).await;
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            String::from_utf8(output.stdout).unwrap()
+        }
+    };
+
+    let json: serde_json::Value = serde_json::from_str(&scan("json").await).unwrap();
+    let reports = json.as_array().unwrap();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0]["subscription"], "drupalsite123");
+    assert_eq!(reports[0]["packages"]["drupal"], "10.2.5");
+    assert_eq!(reports[0]["packages"]["drupal/swiftmailer"], "2.4.0");
+    assert_eq!(reports[0]["services"]["mariadb"], "10.6");
+    assert_eq!(reports[1]["subscription"], "forbidden1234");
+
+    let ndjson = scan("ndjson").await;
+    let lines: Vec<serde_json::Value> = ndjson
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["services"]["redis"], "7.0");
+    // Maps come out in the same order on every run
+    assert!(
+        ndjson.contains(r#""services":{"mariadb":"10.6","redis":"7.0"}"#),
+        "{}",
+        ndjson
+    );
+    assert_eq!(ndjson, scan("ndjson").await);
+
+    let markdown = scan("markdown").await;
+    let lines: Vec<&str> = markdown.lines().collect();
+    assert_eq!(
+        lines[0],
+        "| Subscription | Title | Plan | Storage | Region | Last Backup at | Type | App | mariadb | redis | drupal | drupal/swiftmailer |"
+    );
+    assert_eq!(lines[1], format!("|{}", "---|".repeat(12)));
+    assert_eq!(
+        lines[2],
+        "| drupalsite123 | Drupal Site | medium | 10240 | eu-3.platform.sh | 2024-01-01T12:00:00+00:00 | php:8.2 | drupal | 10.6 | 7.0 | 10.2.5 | 2.4.0 |"
+    );
+
+    let html = scan("html").await;
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<th>drupal/swiftmailer</th>"));
+    assert!(html.contains("<td>Drupal Site</td>"));
+    assert!(html.contains("<script>"));
+    assert!(html.trim_end().ends_with("</html>"));
+}
+
+#[tokio::test]
+async fn reports_advisories() {
+    let server = MockServer::start(Seed::from_yaml(SEED).unwrap()).await;
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("config.yaml"),
+        r#"
+packages:
+  - name: drupal/swiftmailer
+    advisory: SA-CONTRIB-2024-006
+    affected: ">=2.0 <2.4.1 || ^3.0"
+  - name: drupal/swiftmailer
+    advisory: SA-CONTRIB-2023-001
+    affected: "<2.3"
+  - name: drupal/webform
+    advisory: SA-CONTRIB-2024-010
+    affected: "~6.2.0"
+  - name: drupal/swiftmailer
+    advisory: SA-CONTRIB-2025-001
+    affected: "^3.0"
+  - drupal/core
+"#,
+    )
+    .unwrap();
+
+    let output = run_scan(dir.path(), &server, &[]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "Subscription,Title,Plan,Storage,Region,Last Backup at,Type,App,drupal/swiftmailer,drupal/webform,drupal/core,SA-CONTRIB-2024-006,SA-CONTRIB-2023-001,SA-CONTRIB-2024-010,SA-CONTRIB-2025-001"
+    );
+    assert_eq!(
+        &lines[1..],
+        [
+            "drupalsite123,Drupal Site,medium,10240,eu-3.platform.sh,2024-01-01T12:00:00+00:00,php:8.2,drupal,2.4.0,,10.2.5,vulnerable,fixed,unaffected,unaffected",
+            "forbidden1234,Forbidden,standard,5120,eu-3.platform.sh,,,,,,,,,,",
+        ]
+    );
+    assert!(stderr.contains("drupal: SA-CONTRIB-2024-006"), "{}", stderr);
+
+    // The routes inventory doesn't check advisories
+    let output = run_scan(dir.path(), &server, &["--routes"]).await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Nothing vulnerable once the affected range is past the installed version
+    let config = fs::read_to_string(dir.path().join("config.yaml")).unwrap();
+    fs::write(
+        dir.path().join("config.yaml"),
+        config.replace(">=2.0 <2.4.1 || ^3.0", ">=2.0 <2.4.0 || ^3.0"),
+    )
+    .unwrap();
+    let output = run_scan(dir.path(), &server, &[]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        stdout.contains(",2.4.0,,10.2.5,fixed,fixed,unaffected,unaffected"),
+        "{}",
+        stdout
+    );
+}
+
+#[tokio::test]
+async fn unreadable_lock_is_unknown() {
+    let seed = r#"
+organizations:
+  - id: org-adapt
+    name: adapt
+    subscriptions:
+      - { project_id: corruptlock1, title: Corrupt Lock }
+projects:
+  corruptlock1:
+    environments:
+      - { name: main, is_main: true }
+    files:
+      .platform.app.yaml: "name: app\ntype: php:8.3\n"
+      composer.lock: '{"packages": [{"name": "drupal/swiftmailer"'
+"#;
+    let server = MockServer::start(Seed::from_yaml(seed).unwrap()).await;
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("config.yaml"),
+        r#"
+packages:
+  - name: drupal/swiftmailer
+    advisory: SA-CONTRIB-2024-006
+    affected: "<2.5"
+"#,
+    )
+    .unwrap();
+
+    let output = run_scan(dir.path(), &server, &[]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{}", stderr);
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "Subscription,Title,Plan,Storage,Region,Last Backup at,Type,App,drupal/swiftmailer,SA-CONTRIB-2024-006",
+            "corruptlock1,Corrupt Lock,standard,5120,eu-3.platform.sh,2024-01-01T12:00:00+00:00,php:8.3,app,,unknown",
+        ]
+    );
+    assert!(stderr.contains("Unreadable"), "{}", stderr);
+    assert!(stderr.contains("composer.lock"), "{}", stderr);
+    assert!(stderr.contains("app: SA-CONTRIB-2024-006"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn skips_unreadable_projects() {
+    let mut seed = Seed::from_yaml(SEED).unwrap();
+    seed.faults = vec![platform_mock::Fault {
+        path: "/projects/drupalsite123/environments".to_string(),
+        status: 400,
+        ..Default::default()
+    }];
+    let missing_commit = r#"
+environments:
+  - { name: main, is_main: true, head_commit: 0000000000000000000000000000000000000000 }
+"#;
+    seed.projects.insert(
+        "brokenyaml123".to_string(),
+        serde_yaml::from_str(missing_commit).unwrap(),
+    );
+    let server = MockServer::start(seed).await;
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("config.yaml"), CONFIG).unwrap();
+
+    let output = run_scan(dir.path(), &server, &[]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        &lines[1..],
+        [
+            "brokenyaml123,Broken Yaml,standard,5120,eu-3.platform.sh,2024-01-01T12:00:00+00:00,,,,",
+            "drupalsite123,Drupal Site,medium,10240,eu-3.platform.sh,,,,,",
+            "forbidden1234,Forbidden,standard,5120,eu-3.platform.sh,,,,,",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn fails_when_the_token_is_rejected() {
+    let mut seed = Seed::from_yaml(SEED).unwrap();
+    seed.faults = vec![platform_mock::Fault {
+        path: "/projects/drupalsite123/environments".to_string(),
+        status: 401,
+        ..Default::default()
+    }];
+    let server = MockServer::start(seed).await;
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("config.yaml"), CONFIG).unwrap();
+
+    let output = run_scan(dir.path(), &server, &[]).await;
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[tokio::test]
+async fn unreadable_app_root_is_unknown() {
+    let yaml = r#"
+organizations:
+  - id: org-adapt
+    name: adapt
+    subscriptions:
+      - { project_id: upsunsite123, title: Upsun Site }
+projects:
+  upsunsite123:
+    environments:
+      - { name: main, is_main: true }
+    files:
+      .upsun/config.yaml: |
+        applications:
+          cms:
+            type: php:8.3
+            source:
+              root: src/apps/cms
+      src/apps/cms/composer.lock: |
+        {"packages": [{"name": "drupal/swiftmailer", "version": "2.4.0"}]}
+"#;
+    // Git objects only depend on the files, find the sha of the app's root,
+    // deeper than the configuration is looked for
+    let server = MockServer::start(Seed::from_yaml(yaml).unwrap()).await;
+    let client = platform::ApiClient::builder()
+        .api_url(server.url())
+        .auth_url(server.url())
+        .build("api-token")
+        .await
+        .unwrap();
+    let environments: Vec<platform::Environment> = client
+        .get_json("projects/upsunsite123/environments".to_string())
+        .await
+        .unwrap();
+    let head_commit = environments[0].head_commit.as_deref().unwrap();
+    let commit = client
+        .git_commit("upsunsite123", head_commit)
+        .await
+        .unwrap();
+    let root = client
+        .git_tree_lookup("upsunsite123", &commit.tree, "src/apps/cms")
+        .await
+        .unwrap()
+        .unwrap();
+
+    let mut seed = Seed::from_yaml(yaml).unwrap();
+    seed.faults = vec![platform_mock::Fault {
+        path: format!("/projects/upsunsite123/git/trees/{}", root.sha),
+        status: 404,
+        ..Default::default()
+    }];
+    let server = MockServer::start(seed).await;
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("config.yaml"),
+        r#"
+packages:
+  - name: drupal/swiftmailer
+    advisory: SA-CONTRIB-2024-006
+    affected: "<2.5"
+"#,
+    )
+    .unwrap();
+
+    let output = run_scan(dir.path(), &server, &[]).await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{}", stderr);
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "Subscription,Title,Plan,Storage,Region,Last Backup at,Type,App,drupal/swiftmailer,SA-CONTRIB-2024-006",
+            "upsunsite123,Upsun Site,standard,5120,eu-3.platform.sh,2024-01-01T12:00:00+00:00,php:8.3,cms,,unknown",
+        ]
+    );
+    assert!(stderr.contains("Unreadable app root"), "{}", stderr);
+}
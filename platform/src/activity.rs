@@ -0,0 +1,372 @@
+use bytes::{Buf, BytesMut};
+use chrono::{DateTime, Local};
+use futures::stream::{self, BoxStream, Stream, StreamExt, TryStreamExt};
+use serde::Deserialize;
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+use tracing::{debug, instrument, warn};
+
+use url::Url;
+
+use crate::{endpoint_url, Activity, ActivityLogRecord, ApiClient, Error};
+
+/// Activities fetched per request unless [`ActivityFilter::page_size`] says otherwise
+const PAGE_SIZE: usize = 100;
+
+/// Which activities [`ApiClient::activities_stream`] returns. The API lists
+/// activities newest first.
+#[derive(Debug, Clone, Default)]
+pub struct ActivityFilter {
+    /// Activity types, e.g. "environment.push" - any type if empty
+    pub types: Vec<String>,
+    /// "pending", "in_progress", "complete", "cancelled" or "staged"
+    pub state: Option<String>,
+    /// "success" or "failure"
+    pub result: Option<String>,
+    /// Only activities created at or before this time
+    pub before: Option<DateTime<Local>>,
+    /// Only activities created at or after this time
+    pub after: Option<DateTime<Local>>,
+    pub page_size: Option<usize>,
+}
+
+impl ActivityFilter {
+    fn page_size(&self) -> usize {
+        self.page_size.unwrap_or(PAGE_SIZE).max(1)
+    }
+
+    /// Query string for `count` activities created at or before `starts_at`
+    fn query(&self, starts_at: Option<DateTime<Local>>, count: usize) -> String {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        for r#type in self.types.iter() {
+            query.append_pair("type", r#type);
+        }
+        if let Some(state) = &self.state {
+            query.append_pair("state", state);
+        }
+        if let Some(result) = &self.result {
+            query.append_pair("result", result);
+        }
+        if let Some(starts_at) = starts_at {
+            query.append_pair("starts_at", &starts_at.to_rfc3339());
+        }
+        query.append_pair("count", &count.to_string());
+
+        query.finish()
+    }
+}
+
+/// Where the next page of activities starts, and the activities at exactly
+/// that time which were already returned
+struct Cursor {
+    starts_at: Option<DateTime<Local>>,
+    seen: HashSet<String>,
+}
+
+impl Cursor {
+    /// Activities to ask for. The ones already seen come back again, so ask
+    /// for that many more to still make progress when lots of activities
+    /// share a timestamp.
+    fn count(&self, filter: &ActivityFilter) -> usize {
+        filter.page_size() + self.seen.len()
+    }
+}
+
+impl ApiClient {
+    /// Activities of a project, or of a single environment, newest first.
+    ///
+    /// The API pages by creation time rather than with HAL links: each
+    /// following page starts at the creation time of the last activity seen.
+    pub fn activities_stream<'a>(
+        &'a self,
+        project_id: &str,
+        environment_id: Option<&str>,
+        filter: &ActivityFilter,
+    ) -> impl Stream<Item = Result<Activity, Error>> + 'a {
+        let path = match environment_id {
+            Some(environment_id) => format!(
+                "projects/{}/environments/{}/activities",
+                project_id, environment_id
+            ),
+            None => format!("projects/{}/activities", project_id),
+        };
+        let filter = filter.clone();
+        let cursor = Cursor {
+            starts_at: filter.before,
+            seen: HashSet::new(),
+        };
+
+        stream::try_unfold(Some(cursor), move |cursor| {
+            let url = cursor.as_ref().map(|cursor| {
+                let query = filter.query(cursor.starts_at, cursor.count(&filter));
+                format!("{}?{}", path, query)
+            });
+            let filter = filter.clone();
+            async move {
+                let (Some(cursor), Some(url)) = (cursor, url) else {
+                    return Ok::<_, Error>(None); // previous page was the last one
+                };
+                let page: Vec<Activity> = self.get_json(url).await?;
+                let full = page.len() >= cursor.count(&filter);
+
+                let last = page.last().and_then(|activity| activity.created_at);
+                let mut seen: HashSet<String> = page
+                    .iter()
+                    .filter(|activity| activity.created_at == last)
+                    .map(|activity| activity.id.clone())
+                    .collect();
+                if last == cursor.starts_at {
+                    seen.extend(cursor.seen.iter().cloned());
+                }
+
+                let mut activities: Vec<Activity> = page
+                    .into_iter()
+                    .filter(|activity| !cursor.seen.contains(&activity.id))
+                    .collect();
+                let mut done = !full || last.is_none() || activities.is_empty();
+                if let Some(after) = filter.after {
+                    if last.is_some_and(|last| last < after) {
+                        done = true;
+                    }
+                    activities.retain(|activity| activity.created_at.is_none_or(|at| at >= after));
+                }
+
+                let next = (!done).then_some(Cursor {
+                    starts_at: last,
+                    seen,
+                });
+                Ok(Some((stream::iter(activities.into_iter().map(Ok)), next)))
+            }
+        })
+        .try_flatten()
+    }
+
+    #[instrument(skip(self))]
+    pub async fn activities(
+        &self,
+        project_id: &str,
+        environment_id: Option<&str>,
+        filter: &ActivityFilter,
+    ) -> Result<Vec<Activity>, Error> {
+        self.activities_stream(project_id, environment_id, filter)
+            .try_collect()
+            .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn activity(&self, project_id: &str, activity_id: &str) -> Result<Activity, Error> {
+        self.get_json(format!(
+            "projects/{}/activities/{}",
+            project_id, activity_id
+        ))
+        .await
+    }
+
+    /// Poll the activity every `interval` until it is complete or cancelled,
+    /// returning it with its final `result` and `timings`. Whether it
+    /// succeeded is up to the caller to check, see [`Activity::is_success`].
+    #[instrument(skip(self))]
+    pub async fn wait_for_activity(
+        &self,
+        project_id: &str,
+        activity_id: &str,
+        interval: Duration,
+        timeout: Duration,
+    ) -> Result<Activity, Error> {
+        let started = Instant::now();
+        loop {
+            let activity = self.activity(project_id, activity_id).await?;
+            if activity.is_finished() {
+                return Ok(activity);
+            }
+            if started.elapsed() + interval > timeout {
+                return Err(Error::ActivityTimeout {
+                    id: activity.id,
+                    state: activity.state,
+                    waited: started.elapsed(),
+                });
+            }
+
+            debug!(activity.state, activity.completion_percent, "waiting");
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+/// A line of the log stream, either `{"data": {"timestamp": ..., "message":
+/// ...}}` or the closing `{"seal": true}`
+#[derive(Debug, Deserialize)]
+struct LogLine {
+    data: Option<LogData>,
+    #[serde(default)]
+    seal: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogData {
+    timestamp: Option<DateTime<Local>>,
+    #[serde(default)]
+    message: String,
+}
+
+/// State of a log being followed
+struct LogTail {
+    url: String,
+    /// Url of the current connection, for errors
+    endpoint: Option<url::Url>,
+    /// Records received so far, to resume from if the connection drops
+    received: usize,
+    body: Option<BoxStream<'static, reqwest::Result<bytes::Bytes>>>,
+    buffer: BytesMut,
+    records: VecDeque<ActivityLogRecord>,
+    /// Records received over the current connection
+    fresh: usize,
+    sealed: bool,
+}
+
+impl LogTail {
+    /// Parse every complete line in the buffer
+    fn parse_lines(&mut self) -> Result<(), Error> {
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line = self.buffer.split_to(end + 1);
+            let line = line.chunk().trim_ascii();
+            if line.is_empty() {
+                continue;
+            }
+            let line: LogLine =
+                serde_json::from_slice(line).map_err(|source| Error::Deserialize {
+                    url: self.endpoint.clone().expect("connected before parsing"),
+                    source,
+                })?;
+
+            self.received += 1;
+            self.fresh += 1;
+            let data = line.data.unwrap_or(LogData {
+                timestamp: None,
+                message: String::new(),
+            });
+            self.sealed = line.seal;
+            self.records.push_back(ActivityLogRecord {
+                timestamp: data.timestamp,
+                message: data.message,
+                seal: line.seal,
+            });
+            if self.sealed {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The log at `url` from record `start_at` on, keeping any other parameters
+fn resume_at(mut url: Url, start_at: usize) -> Url {
+    let query: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| name != "start_at")
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(query)
+        .append_pair("start_at", &start_at.to_string());
+    url
+}
+
+impl ApiClient {
+    /// Follow the log of an activity as it is written, like `tail -f`.
+    ///
+    /// The stream ends after the record with `seal` set. If the connection
+    /// drops before that, the log is requested again from where it left off,
+    /// and if that doesn't bring any new records the stream ends with
+    /// [`Error::LogInterrupted`].
+    pub async fn activity_log_stream(
+        &self,
+        project_id: &str,
+        activity_id: &str,
+    ) -> Result<impl Stream<Item = Result<ActivityLogRecord, Error>> + '_, Error> {
+        let activity = self.activity(project_id, activity_id).await?;
+        let url = activity
+            ._links
+            .get("log")
+            .map(|log| log.href.clone())
+            .ok_or_else(|| Error::NotFound)?;
+
+        let tail = LogTail {
+            url,
+            endpoint: None,
+            received: 0,
+            body: None,
+            buffer: BytesMut::new(),
+            records: VecDeque::new(),
+            fresh: 0,
+            sealed: false,
+        };
+
+        Ok(stream::try_unfold(tail, move |mut tail| async move {
+            loop {
+                if let Some(record) = tail.records.pop_front() {
+                    return Ok(Some((record, tail)));
+                }
+                if tail.sealed {
+                    return Ok(None);
+                }
+
+                let Some(body) = tail.body.as_mut() else {
+                    let mut url = endpoint_url(&self.api_url, &tail.url)?;
+                    if tail.received > 0 {
+                        url = resume_at(url, tail.received);
+                    }
+                    debug!(%url, "following activity log");
+                    let response = self.get(url.to_string()).await?;
+                    tail.endpoint = Some(response.url().clone());
+                    tail.body = Some(response.bytes_stream().boxed());
+                    tail.fresh = 0;
+                    continue;
+                };
+
+                let error = match body.next().await {
+                    Some(Ok(chunk)) => {
+                        tail.buffer.extend_from_slice(&chunk);
+                        tail.parse_lines()?;
+                        continue;
+                    }
+                    Some(Err(error)) => Some(error),
+                    None => None,
+                };
+
+                // Connection dropped or closed before the seal. Reconnect,
+                // unless it didn't get us anywhere last time either.
+                if tail.fresh == 0 {
+                    return Err(Error::LogInterrupted {
+                        url: tail.endpoint.clone().expect("connected before reading"),
+                        received: tail.received,
+                    });
+                }
+                warn!(received = tail.received, ?error, "activity log interrupted");
+                tail.body = None;
+                tail.buffer.clear();
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resumes_log_keeping_the_query() {
+        let resume = |url: &str| resume_at(Url::parse(url).unwrap(), 12).to_string();
+
+        assert_eq!(
+            resume("https://api.platform.sh/projects/p/activities/a/log"),
+            "https://api.platform.sh/projects/p/activities/a/log?start_at=12"
+        );
+        assert_eq!(
+            resume("https://api.platform.sh/projects/p/activities/a/log?token=x%2By&start_at=3"),
+            "https://api.platform.sh/projects/p/activities/a/log?token=x%2By&start_at=12"
+        );
+    }
+}
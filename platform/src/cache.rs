@@ -0,0 +1,263 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tracing::{debug, info, warn};
+
+/// Tells apart the temporary files of writes in this process
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// On-disk cache for git commits, trees and blobs.
+///
+/// These are addressed by SHA and never change, so entries are never
+/// invalidated - only evicted, least recently used first, once the cache grows
+/// beyond `max_size` bytes. Entries live in `<dir>/<project>/<kind>/<sha>.json`.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    max_size: u64,
+    // Current size in bytes, computed on first write
+    size: Arc<Mutex<Option<u64>>>,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>, max_size: u64) -> Cache {
+        Cache {
+            dir: dir.into(),
+            max_size,
+            size: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, project_id: &str, kind: &str, sha: &str) -> PathBuf {
+        self.dir
+            .join(project_id)
+            .join(kind)
+            .join(format!("{}.json", sha))
+    }
+
+    /// Cached object, if any. Unreadable entries are treated as missing.
+    pub(crate) async fn get<T: DeserializeOwned>(
+        &self,
+        project_id: &str,
+        kind: &str,
+        sha: &str,
+    ) -> Option<T> {
+        let path = self.path(project_id, kind, sha);
+        let buffer = tokio::fs::read(&path).await.ok()?;
+
+        match serde_json::from_slice(&buffer) {
+            Ok(value) => {
+                debug!(path = %path.display(), "cache hit");
+                // Bump mtime so eviction sees this entry as recently used
+                let _ = tokio::task::spawn_blocking(move || {
+                    if let Ok(file) = fs::File::options().write(true).open(&path) {
+                        let _ = file.set_modified(SystemTime::now());
+                    }
+                })
+                .await;
+                Some(value)
+            }
+            Err(error) => {
+                warn!(path = %path.display(), %error, "ignoring corrupt cache entry");
+                None
+            }
+        }
+    }
+
+    /// Store an object. Failing to do so is logged, but otherwise ignored -
+    /// the cache is only an optimisation.
+    pub(crate) async fn put<T: Serialize>(
+        &self,
+        project_id: &str,
+        kind: &str,
+        sha: &str,
+        value: &T,
+    ) {
+        let path = self.path(project_id, kind, sha);
+        let buffer = match serde_json::to_vec(value) {
+            Ok(buffer) => buffer,
+            Err(error) => {
+                warn!(%error, "unable to serialize cache entry");
+                return;
+            }
+        };
+        let cache = self.clone();
+        let result = tokio::task::spawn_blocking(move || cache.write(&path, &buffer)).await;
+
+        match result {
+            Ok(Err(error)) => warn!(%error, "unable to write cache entry"),
+            Err(error) => warn!(%error, "unable to write cache entry"),
+            Ok(Ok(())) => {}
+        }
+    }
+
+    /// Write an entry, evicting old entries if the cache grows over the limit
+    fn write(&self, path: &Path, buffer: &[u8]) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write to a temporary file and rename, so concurrent readers never
+        // see a half written entry
+        let tmp = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&tmp, buffer)?;
+
+        // Replacing and accounting under the lock, so that concurrent writes
+        // of the same entry don't both count it as new
+        let mut size = self.size.lock().unwrap();
+        let replaced = fs::metadata(path).map_or(0, |metadata| metadata.len());
+        if let Err(error) = fs::rename(&tmp, path) {
+            let _ = fs::remove_file(&tmp);
+            return Err(error);
+        }
+        let current = match *size {
+            Some(current) => (current + buffer.len() as u64).saturating_sub(replaced),
+            None => entries(&self.dir)?.iter().map(|(_, len, _)| len).sum(),
+        };
+
+        *size = Some(if current > self.max_size {
+            self.evict()?
+        } else {
+            current
+        });
+
+        Ok(())
+    }
+
+    /// Remove least recently used entries until the cache is below 90% of
+    /// `max_size`, returning the new size
+    fn evict(&self) -> io::Result<u64> {
+        let mut entries = entries(&self.dir)?;
+        entries.sort_by_key(|(_, _, modified)| *modified);
+
+        let target = self.max_size / 10 * 9;
+        let mut size: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        let mut removed = 0;
+        for (path, len, _) in entries {
+            if size <= target {
+                break;
+            }
+            fs::remove_file(&path)?;
+            size -= len;
+            removed += 1;
+        }
+        info!(removed, size, "evicted cache entries");
+
+        Ok(size)
+    }
+}
+
+/// Every entry in the cache with its size and modification time
+fn entries(dir: &Path) -> io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+    let mut entries = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let read_dir = match fs::read_dir(&dir) {
+            Ok(read_dir) => read_dir,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error),
+        };
+        for entry in read_dir {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else if entry.path().extension().is_some_and(|ext| ext == "json") {
+                entries.push((entry.path(), metadata.len(), metadata.modified()?));
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn size(dir: &Path) -> u64 {
+        entries(dir).unwrap().iter().map(|(_, len, _)| len).sum()
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path(), 1024 * 1024);
+
+        assert_eq!(cache.get::<String>("project", "blobs", "abc").await, None);
+        cache.put("project", "blobs", "abc", &"content").await;
+        assert_eq!(
+            cache
+                .get::<String>("project", "blobs", "abc")
+                .await
+                .as_deref(),
+            Some("content")
+        );
+        assert!(dir.path().join("project/blobs/abc.json").exists());
+    }
+
+    #[tokio::test]
+    async fn corrupt_entry_is_a_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path(), 1024 * 1024);
+
+        cache.put("project", "trees", "abc", &vec![1, 2, 3]).await;
+        fs::write(dir.path().join("project/trees/abc.json"), b"[1, 2,").unwrap();
+        assert_eq!(cache.get::<Vec<u32>>("project", "trees", "abc").await, None);
+    }
+
+    #[tokio::test]
+    async fn evicts_least_recently_used_down_to_90_percent() {
+        let dir = tempfile::tempdir().unwrap();
+        // Each entry is the 98 character string plus its quotes
+        let cache = Cache::new(dir.path(), 1000);
+        let value = "x".repeat(98);
+
+        for i in 0..10 {
+            cache.put("project", "blobs", &i.to_string(), &value).await;
+            // Distinct modification times
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(size(dir.path()), 1000);
+        // Used recently, so kept
+        assert!(cache.get::<String>("project", "blobs", "0").await.is_some());
+
+        cache.put("project", "blobs", "10", &value).await;
+        assert_eq!(size(dir.path()), 900);
+        let mut left: Vec<u32> = entries(dir.path())
+            .unwrap()
+            .iter()
+            .map(|(path, _, _)| path.file_stem().unwrap().to_str().unwrap().parse().unwrap())
+            .collect();
+        left.sort();
+        assert_eq!(left, [0, 3, 4, 5, 6, 7, 8, 9, 10]);
+    }
+
+    #[tokio::test]
+    async fn replacing_an_entry_counts_it_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path(), 1000);
+
+        cache
+            .put("project", "blobs", "first", &"x".repeat(98))
+            .await;
+        for _ in 0..20 {
+            cache.put("project", "blobs", "abc", &"x".repeat(98)).await;
+        }
+        assert_eq!(*cache.size.lock().unwrap(), Some(200));
+        assert!(dir.path().join("project/blobs/first.json").exists());
+    }
+}
@@ -0,0 +1,304 @@
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+use tracing::{instrument, warn};
+
+use crate::{ApiClient, Error, GitTreeItem, PlatformApp, PlatformService, Route};
+
+/// An app, where it was declared and where its source lives
+#[derive(Debug)]
+pub struct ConfiguredApp {
+    pub app: PlatformApp,
+    /// File declaring the app, e.g. `web/.platform.app.yaml`
+    pub file: String,
+    /// Directory of the app's source relative to the repository root, "" for
+    /// the root itself
+    pub root: String,
+}
+
+/// Apps, services and routes of a project, whichever of the layouts declares
+/// them:
+///
+/// - `.platform.app.yaml` in the directory of each app, with
+///   `.platform/services.yaml` and `.platform/routes.yaml`
+/// - a list of apps in `.platform/applications.yaml`, each with its
+///   `source.root`
+/// - Upsun's `applications`, `services` and `routes` in `.upsun/config.yaml`
+///   (or any other `.upsun/*.yaml`)
+#[derive(Debug, Default)]
+pub struct ProjectConfig {
+    pub apps: Vec<ConfiguredApp>,
+    pub services: HashMap<String, PlatformService>,
+    /// Keyed by url pattern, e.g. `https://www.{default}/`
+    pub routes: HashMap<String, Route>,
+    /// Files, or entries in them, that couldn't be parsed
+    pub unreadable: Vec<String>,
+}
+
+/// A route with its placeholders filled in
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedRoute {
+    /// As in routes.yaml
+    pub pattern: String,
+    pub url: String,
+    /// Where a redirect route goes, resolved too
+    pub to: Option<String>,
+    pub route: Route,
+}
+
+impl ResolvedRoute {
+    /// Host part of `url`, e.g. `www.example.com` or `*.example.com`
+    pub fn host(&self) -> &str {
+        let rest = self
+            .url
+            .split_once("://")
+            .map_or(self.url.as_str(), |(_, rest)| rest);
+        rest.split(['/', ':']).next().unwrap_or(rest)
+    }
+}
+
+/// Whether failing to fetch a single file should fail the whole project,
+/// rather than leave the file unreadable
+fn is_fatal(error: &Error) -> bool {
+    error.is_unavailable() || matches!(error, Error::Auth { .. } | Error::MissingFixture(_))
+}
+
+/// `source.root` as a path relative to the repository root
+fn normalize_root(root: &str) -> String {
+    root.trim_matches('/').trim_start_matches("./").to_string()
+}
+
+impl ProjectConfig {
+    fn unreadable(&mut self, path: String, error: impl std::fmt::Display) {
+        warn!(%error, path, "Unreadable configuration file");
+        self.unreadable.push(path);
+    }
+
+    /// An app from a mapping, named `name` unless it has a name of its own
+    fn add_app_value(&mut self, file: &str, path: String, name: Option<&Value>, value: Value) {
+        let value = match (value, name) {
+            (Value::Mapping(mut mapping), Some(name)) => {
+                if !mapping.contains_key("name") {
+                    mapping.insert("name".into(), name.clone());
+                }
+                Value::Mapping(mapping)
+            }
+            (value, _) => value,
+        };
+        match serde_yaml::from_value::<PlatformApp>(value) {
+            Ok(app) => {
+                let root = app
+                    .source
+                    .as_ref()
+                    .and_then(|source| source.root.as_deref())
+                    .map(normalize_root)
+                    .unwrap_or_default();
+                self.apps.push(ConfiguredApp {
+                    app,
+                    file: file.to_string(),
+                    root,
+                });
+            }
+            Err(error) => self.unreadable(path, error),
+        }
+    }
+
+    /// A `.platform.app.yaml` at `path`, the app's root being its directory
+    pub fn add_app_yaml(&mut self, path: &str, content: &[u8]) {
+        match serde_yaml::from_slice::<PlatformApp>(content) {
+            Ok(app) => self.apps.push(ConfiguredApp {
+                app,
+                file: path.to_string(),
+                root: path
+                    .rsplit_once('/')
+                    .map(|(dir, _)| normalize_root(dir))
+                    .unwrap_or_default(),
+            }),
+            Err(error) => self.unreadable(path.to_string(), error),
+        }
+    }
+
+    /// `.platform/applications.yaml`, a list of apps
+    pub fn add_applications_yaml(&mut self, path: &str, content: &[u8]) {
+        match serde_yaml::from_slice::<Vec<Value>>(content) {
+            Ok(apps) => {
+                for (i, app) in apps.into_iter().enumerate() {
+                    self.add_app_value(path, format!("{}[{}]", path, i), None, app);
+                }
+            }
+            Err(error) => self.unreadable(path.to_string(), error),
+        }
+    }
+
+    /// `.platform/services.yaml`
+    pub fn add_services_yaml(&mut self, path: &str, content: &[u8]) {
+        match serde_yaml::from_slice::<HashMap<String, PlatformService>>(content) {
+            Ok(services) => self.services.extend(services),
+            Err(error) => self.unreadable(path.to_string(), error),
+        }
+    }
+
+    /// `.platform/routes.yaml`
+    pub fn add_routes_yaml(&mut self, path: &str, content: &[u8]) {
+        match serde_yaml::from_slice::<HashMap<String, Route>>(content) {
+            Ok(routes) => self.routes.extend(routes),
+            Err(error) => self.unreadable(path.to_string(), error),
+        }
+    }
+
+    /// One of the `.upsun/*.yaml` files, which together make up the
+    /// configuration
+    pub fn add_upsun_yaml(&mut self, path: &str, content: &[u8]) {
+        let mut config = match serde_yaml::from_slice::<Mapping>(content) {
+            Ok(config) => config,
+            Err(error) => return self.unreadable(path.to_string(), error),
+        };
+
+        if let Some(applications) = config.remove("applications") {
+            match applications {
+                Value::Mapping(applications) => {
+                    for (name, app) in applications {
+                        let entry = format!(
+                            "{}#applications.{}",
+                            path,
+                            name.as_str().unwrap_or_default()
+                        );
+                        self.add_app_value(path, entry, Some(&name), app);
+                    }
+                }
+                _ => self.unreadable(format!("{}#applications", path), "not a mapping"),
+            }
+        }
+        if let Some(services) = config.remove("services") {
+            match serde_yaml::from_value::<HashMap<String, PlatformService>>(services) {
+                Ok(services) => self.services.extend(services),
+                Err(error) => self.unreadable(format!("{}#services", path), error),
+            }
+        }
+        if let Some(routes) = config.remove("routes") {
+            match serde_yaml::from_value::<HashMap<String, Route>>(routes) {
+                Ok(routes) => self.routes.extend(routes),
+                Err(error) => self.unreadable(format!("{}#routes", path), error),
+            }
+        }
+    }
+
+    /// Every route with `{default}` replaced by `default` and `{all}` by each
+    /// of `all` in turn, sorted by url. Without any `all` domains, `{all}`
+    /// means `default`.
+    pub fn resolve_routes(&self, default: &str, all: &[String]) -> Vec<ResolvedRoute> {
+        let all = if all.is_empty() {
+            vec![default.to_string()]
+        } else {
+            all.to_vec()
+        };
+
+        let mut resolved = Vec::new();
+        for (pattern, route) in self.routes.iter() {
+            let domains = if pattern.contains("{all}") {
+                all.as_slice()
+            } else {
+                std::slice::from_ref(&all[0])
+            };
+            for domain in domains {
+                let resolve = |pattern: &str| {
+                    pattern
+                        .replace("{default}", default)
+                        .replace("{all}", domain)
+                };
+                resolved.push(ResolvedRoute {
+                    pattern: pattern.clone(),
+                    url: resolve(pattern),
+                    to: route.to.as_deref().map(resolve),
+                    route: route.clone(),
+                });
+            }
+        }
+        resolved.sort_by(|a, b| a.url.cmp(&b.url));
+        resolved
+    }
+}
+
+impl ApiClient {
+    /// Item at a `/` separated `path` below `tree`
+    #[instrument(skip(self))]
+    pub async fn git_tree_lookup(
+        &self,
+        project_id: &str,
+        tree: &str,
+        path: &str,
+    ) -> Result<Option<GitTreeItem>, Error> {
+        let mut item: Option<GitTreeItem> = None;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let tree = match &item {
+                None => tree,
+                Some(item) if item.r#type == "tree" => &item.sha,
+                Some(_) => return Ok(None),
+            };
+            match self.git_tree_lookup_path(project_id, tree, name).await? {
+                Some(found) => item = Some(found),
+                None => return Ok(None),
+            }
+        }
+        Ok(item)
+    }
+
+    /// Configuration of the project at the commit with `tree`. Files that
+    /// can't be fetched or parsed end up in [`ProjectConfig::unreadable`],
+    /// only the API being unavailable or refusing the token fails.
+    #[instrument(skip(self))]
+    pub async fn project_config(
+        &self,
+        project_id: &str,
+        tree: &str,
+    ) -> Result<ProjectConfig, Error> {
+        let mut config = ProjectConfig::default();
+
+        for dir in [".upsun", ".platform"] {
+            let Some(item) = self.git_tree_lookup_path(project_id, tree, dir).await? else {
+                continue;
+            };
+            if item.r#type != "tree" {
+                continue;
+            }
+            for file in self.git_tree(project_id, &item.sha).await?.tree {
+                if file.r#type != "blob" {
+                    continue;
+                }
+                let path = format!("{}/{}", dir, file.path);
+                let add = match (dir, file.path.as_str()) {
+                    (".upsun", name) if name.ends_with(".yaml") => ProjectConfig::add_upsun_yaml,
+                    (".platform", "applications.yaml") => ProjectConfig::add_applications_yaml,
+                    (".platform", "services.yaml") => ProjectConfig::add_services_yaml,
+                    (".platform", "routes.yaml") => ProjectConfig::add_routes_yaml,
+                    _ => continue,
+                };
+                match self.git_blob_decode(project_id, &file.sha).await {
+                    Err(error) if is_fatal(&error) => return Err(error),
+                    Err(error) => config.unreadable(path, error),
+                    Ok(content) => add(&mut config, &path, &content),
+                }
+            }
+        }
+
+        let app_files = self
+            .git_tree_find(
+                project_id,
+                tree,
+                |path| path == ".platform.app.yaml",
+                2,
+                "".to_string(),
+            )
+            .await?;
+        for file in app_files {
+            let path = file.fullpath.trim_start_matches('/');
+            match self.git_blob_decode(project_id, &file.sha).await {
+                Err(error) if is_fatal(&error) => return Err(error),
+                Err(error) => config.unreadable(path.to_string(), error),
+                Ok(content) => config.add_app_yaml(path, &content),
+            }
+        }
+
+        Ok(config)
+    }
+}
@@ -0,0 +1,143 @@
+use reqwest::{Response, StatusCode};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+use url::Url;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Reqwest(#[from] reqwest::Error),
+    #[error(transparent)]
+    Base64(#[from] base64::DecodeError),
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Not found")]
+    NotFound,
+    /// The API answered with a non-success status
+    #[error("{status} from {url}{}", .message.as_deref().map(|m| format!(": {}", m)).unwrap_or_default())]
+    Api {
+        url: Url,
+        status: StatusCode,
+        message: Option<String>,
+    },
+    /// The API token was rejected, or the access token still was after a refresh
+    #[error("Authentication failed ({status}){}", .message.as_deref().map(|m| format!(": {}", m)).unwrap_or_default())]
+    Auth {
+        status: StatusCode,
+        message: Option<String>,
+    },
+    /// Still rate limited after all retries
+    #[error("Rate limited by {url}")]
+    RateLimited {
+        url: Url,
+        retry_after: Option<Duration>,
+    },
+    #[error("Unable to decode response from {url}: {source}")]
+    Deserialize {
+        url: Url,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// The API accepted a request, but didn't say which activity handles it
+    #[error("No activity in response from {0}")]
+    NoActivity(Url),
+    /// Gave up waiting for an activity to finish
+    #[error("Activity {id} still {state} after {waited:?}")]
+    ActivityTimeout {
+        id: String,
+        state: String,
+        waited: Duration,
+    },
+    /// The connection to an activity log closed before the last record, and
+    /// again without any new records after reconnecting
+    #[error("Log from {url} interrupted after {received} records")]
+    LogInterrupted { url: Url, received: usize },
+    /// Replaying, but no response was recorded for the request
+    #[error("No fixture {}", .0.display())]
+    MissingFixture(PathBuf),
+}
+
+/// Error body returned by the API (and the OAuth2 server), e.g.
+/// `{"status": "error", "code": 403, "message": "..."}`
+#[derive(Debug, Deserialize)]
+struct ApiErrorBody {
+    message: Option<String>,
+    title: Option<String>,
+    error_description: Option<String>,
+    error: Option<String>,
+}
+
+impl Error {
+    /// HTTP status of the response that caused the error, if any
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Reqwest(error) => error.status(),
+            Error::Api { status, .. } | Error::Auth { status, .. } => Some(*status),
+            Error::RateLimited { .. } => Some(StatusCode::TOO_MANY_REQUESTS),
+            _ => None,
+        }
+    }
+
+    /// No access to the resource, e.g. a project we are not a member of
+    pub fn is_forbidden(&self) -> bool {
+        self.status() == Some(StatusCode::FORBIDDEN)
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Error::NotFound) || self.status() == Some(StatusCode::NOT_FOUND)
+    }
+
+    /// The API is down, overloaded or unreachable - as opposed to refusing
+    /// the request. Worth trying again later.
+    pub fn is_unavailable(&self) -> bool {
+        match self {
+            Error::Reqwest(error) => error.is_connect() || error.is_timeout(),
+            Error::RateLimited { .. } => true,
+            _ => self.status().is_some_and(|status| status.is_server_error()),
+        }
+    }
+
+    /// Turn an unsuccessful response into an error, keeping the message from
+    /// the body if the API sent one.
+    pub(crate) async fn from_response(url: Url, response: Response) -> Error {
+        let status = response.status();
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = crate::retry::retry_after(&response);
+            return Error::RateLimited { url, retry_after };
+        }
+
+        let message = response.bytes().await.ok().and_then(|body| {
+            match serde_json::from_slice::<ApiErrorBody>(&body) {
+                Ok(body) => body
+                    .message
+                    .or(body.error_description)
+                    .or(body.title)
+                    .or(body.error),
+                // Probably an HTML error page from a proxy, keep it short
+                Err(_) => Some(
+                    String::from_utf8_lossy(&body)
+                        .trim()
+                        .chars()
+                        .take(200)
+                        .collect(),
+                )
+                .filter(|text: &String| !text.is_empty()),
+            }
+        });
+
+        if status == StatusCode::UNAUTHORIZED {
+            Error::Auth { status, message }
+        } else {
+            Error::Api {
+                url,
+                status,
+                message,
+            }
+        }
+    }
+}
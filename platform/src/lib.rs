@@ -1,14 +1,32 @@
 use async_recursion::async_recursion;
 use base64::{engine::general_purpose, Engine as _};
-use reqwest::{Client, RequestBuilder};
-use std::vec;
-use thiserror::Error;
-use tracing::{debug, info, instrument};
+use futures::stream::{self, Stream, TryStreamExt};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{debug, info, instrument, warn};
 use url::Url;
 
+mod activity;
+mod cache;
+mod config;
+mod error;
 mod model;
-
+mod retry;
+mod transport;
+mod variable;
+
+pub use crate::activity::ActivityFilter;
+pub use crate::cache::Cache;
+pub use crate::config::{ConfiguredApp, ProjectConfig, ResolvedRoute};
+pub use crate::error::Error;
 pub use crate::model::*;
+pub use crate::retry::RetryPolicy;
+pub use crate::transport::{Fixture, Transport};
+pub use crate::variable::VariableRequest;
 
 // TODO impl TryFrom<HALLink> for Url - std::convert::TryFrom()
 // impl TryFrom<HALLink> for Url {
@@ -25,12 +43,51 @@ pub use crate::model::*;
 //     }
 // }
 
+const API_URL: &str = "https://api.platform.sh";
+const AUTH_URL: &str = "https://auth.api.platform.sh";
+
+/// Refresh the access token this long before it actually expires, so a
+/// request started just before expiry doesn't get rejected in flight. At most
+/// half the token's lifetime, so short lived tokens still get used.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Cheap to clone - clones share the same access token, so a refresh done by
+/// one task is picked up by all of them.
 #[derive(Debug, Clone)]
 pub struct ApiClient {
-    #[allow(dead_code)]
     api_token: String,
-    oauth2: Oauth2,
+    token: Arc<RwLock<Token>>,
     client: Client,
+    api_url: Url,
+    auth_url: Url,
+    retry: RetryPolicy,
+    cache: Option<Cache>,
+    transport: Transport,
+}
+
+#[derive(Debug)]
+struct Token {
+    oauth2: Oauth2,
+    issued_at: Instant,
+}
+
+impl Token {
+    fn expires_soon(&self) -> bool {
+        let lifetime = Duration::from_secs(self.oauth2.expires_in.max(0) as u64);
+        self.issued_at.elapsed() + TOKEN_REFRESH_MARGIN.min(lifetime / 2) >= lifetime
+    }
+}
+
+/// Builder for [`ApiClient`], mainly for pointing it at something other than
+/// api.platform.sh - Upsun, a regional/proxy endpoint or a local mock server.
+#[derive(Debug, Clone)]
+pub struct ApiClientBuilder {
+    api_url: String,
+    auth_url: String,
+    client: Option<Client>,
+    retry: RetryPolicy,
+    cache: Option<Cache>,
+    transport: Transport,
 }
 
 #[derive(Debug)]
@@ -44,173 +101,445 @@ pub struct GitSearchResult {
     pub fullpath: String,
 }
 
-#[derive(Error, Debug)]
-pub enum Error {
-    #[error(transparent)]
-    Reqwest(#[from] reqwest::Error),
-    #[error(transparent)]
-    Base64(#[from] base64::DecodeError),
-    #[error("Not found")]
-    NotFound,
+impl Default for ApiClientBuilder {
+    fn default() -> Self {
+        ApiClientBuilder {
+            api_url: API_URL.to_string(),
+            auth_url: AUTH_URL.to_string(),
+            client: None,
+            retry: RetryPolicy::default(),
+            cache: None,
+            transport: Transport::default(),
+        }
+    }
 }
 
-impl ApiClient {
-    #[instrument]
-    pub async fn new(api_token: &str) -> Result<ApiClient, reqwest::Error> {
-        let client = reqwest::Client::new();
-
-        // eprint!("get oauth2 token... ");
-        let oauth2: Oauth2 = client
-            .post("https://auth.api.platform.sh/oauth2/token")
-            .basic_auth("platform-api-user", None::<String>)
-            .form(&[("grant_type", "api_token"), ("api_token", api_token)])
-            .send()
-            .await?
-            .json()
-            .await?;
-        // eprintln!("ok");
-        // eprintln!("{:#?}", oauth2);
+impl ApiClientBuilder {
+    /// Base url for the REST API, defaults to https://api.platform.sh
+    pub fn api_url(mut self, url: impl Into<String>) -> Self {
+        self.api_url = url.into();
+        self
+    }
+
+    /// Base url for the OAuth2 server, defaults to https://auth.api.platform.sh
+    pub fn auth_url(mut self, url: impl Into<String>) -> Self {
+        self.auth_url = url.into();
+        self
+    }
+
+    /// Use a preconfigured reqwest client (proxy, timeouts, ...)
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// How GET requests are retried, see [`RetryPolicy::default`]
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
 
-        Ok(ApiClient {
+    /// Cache git commits, trees and blobs on disk
+    pub fn cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Record responses to, or replay them from, fixture files
+    pub fn transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    #[instrument(skip(self, api_token))]
+    pub async fn build(self, api_token: &str) -> Result<ApiClient, Error> {
+        let client = ApiClient {
             api_token: api_token.to_string(),
-            oauth2,
-            client,
-        })
+            token: Arc::new(RwLock::new(Token {
+                oauth2: Oauth2 {
+                    access_token: String::new(),
+                    expires_in: 0,
+                    token_type: String::new(),
+                },
+                issued_at: Instant::now(),
+            })),
+            client: self.client.unwrap_or_default(),
+            api_url: base_url(&self.api_url)?,
+            auth_url: base_url(&self.auth_url)?,
+            retry: self.retry,
+            cache: self.cache,
+            transport: self.transport,
+        };
+        client.re_authenticate().await?;
+
+        Ok(client)
+    }
+}
+
+/// Join `url` onto `base`. Absolute urls outside of the base's path, such as
+/// HAL links from an API behind a proxy at `https://host/platform/`, which
+/// know nothing of the proxy, are rebased onto it.
+fn endpoint_url(base: &Url, url: &str) -> Result<Url, url::ParseError> {
+    let joined = base.join(url)?;
+    if joined.path().starts_with(base.path()) {
+        return Ok(joined);
+    }
+    let mut rebased = base.join(joined.path().trim_start_matches('/'))?;
+    rebased.set_query(joined.query());
+    Ok(rebased)
+}
+
+/// Parse a base url, making sure it ends in a slash so relative paths are
+/// joined onto it instead of replacing the last segment.
+fn base_url(url: &str) -> Result<Url, url::ParseError> {
+    if url.ends_with('/') {
+        Url::parse(url)
+    } else {
+        Url::parse(&format!("{}/", url))
+    }
+}
+
+impl ApiClient {
+    pub fn builder() -> ApiClientBuilder {
+        ApiClientBuilder::default()
+    }
+
+    #[instrument(skip(api_token))]
+    pub async fn new(api_token: &str) -> Result<ApiClient, Error> {
+        ApiClient::builder().build(api_token).await
     }
 
+    pub fn api_url(&self) -> &Url {
+        &self.api_url
+    }
+
+    /// Exchange the API token for a fresh access token
     #[instrument(skip(self))]
-    pub async fn re_authenticate(&mut self) -> Result<(), reqwest::Error> {
-        self.oauth2 = self
-            .client
-            .post("https://auth.api.platform.sh/oauth2/token")
-            .basic_auth("platform-api-user", None::<String>)
-            .form(&[("grant_type", "api_token"), ("api_token", &self.api_token)])
-            .send()
-            .await?
-            .json()
-            .await?;
+    pub async fn re_authenticate(&self) -> Result<(), Error> {
+        let mut token = self.token.write().await;
+        *token = self.fetch_token().await?;
         Ok(())
     }
 
+    async fn fetch_token(&self) -> Result<Token, Error> {
+        let token_url = self
+            .auth_url
+            .join("oauth2/token")
+            .expect("oauth2/token is a valid relative url");
+        let issued_at = Instant::now();
+        let response = self
+            .send(
+                self.client
+                    .post(token_url.clone())
+                    .basic_auth("platform-api-user", None::<String>)
+                    .form(&[("grant_type", "api_token"), ("api_token", &self.api_token)]),
+            )
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(match Error::from_response(token_url, response).await {
+                Error::Api {
+                    status, message, ..
+                } if status.is_client_error() => Error::Auth { status, message },
+                error => error,
+            });
+        }
+        let oauth2: Oauth2 = decode(response).await?;
+        debug!(expires_in = oauth2.expires_in, "got access token");
+
+        Ok(Token { oauth2, issued_at })
+    }
+
+    /// Current access token, refreshed first if it is about to expire
+    async fn access_token(&self) -> Result<String, Error> {
+        let stale = {
+            let token = self.token.read().await;
+            if !token.expires_soon() {
+                return Ok(token.oauth2.access_token.clone());
+            }
+            token.oauth2.access_token.clone()
+        };
+
+        self.refresh_token(&stale).await
+    }
+
+    /// Replace `stale` with a new access token. If another task got here first
+    /// and already replaced it, its token is used instead of fetching again.
+    async fn refresh_token(&self, stale: &str) -> Result<String, Error> {
+        let mut token = self.token.write().await;
+        if token.oauth2.access_token == stale {
+            info!("refreshing access token");
+            *token = self.fetch_token().await?;
+        }
+
+        Ok(token.oauth2.access_token.clone())
+    }
+
+    /// GET `url` (relative to the API url), retrying 5xx, 429 and connection
+    /// errors according to the client's [`RetryPolicy`]. Any response that is
+    /// still unsuccessful after that is turned into an [`Error`].
     #[instrument(skip(self))]
-    pub fn get(&self, url: String) -> RequestBuilder {
-        let options = Url::options();
-        let api = Url::parse("https://api.platform.sh").unwrap();
-        let base_url = options.base_url(Some(&api));
-        let endpoint_url = base_url.parse(&url).unwrap();
+    pub async fn get(&self, url: String) -> Result<Response, Error> {
+        let endpoint_url = endpoint_url(&self.api_url, &url)?;
 
         debug!(url);
-        self.client
-            .get(endpoint_url)
-            .bearer_auth(&self.oauth2.access_token)
+        let response = self.get_retrying(endpoint_url.clone()).await?;
+
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            Err(Error::from_response(endpoint_url, response).await)
+        }
     }
 
-    #[instrument(skip(self))]
-    pub async fn organizations(&self) -> Result<Vec<Organization>, reqwest::Error> {
-        // Really ought to return a Stream/Iterator
+    /// GET `url` and deserialize the JSON response
+    pub async fn get_json<T: DeserializeOwned>(&self, url: String) -> Result<T, Error> {
+        let response = self.get(url).await?;
 
-        let mut organizations: Vec<Organization> = vec![];
-        let mut url = "https://api.platform.sh/organizations".to_string();
+        decode(response).await
+    }
 
-        // eprintln!("Getting organizations...");
+    async fn get_retrying(&self, endpoint_url: Url) -> Result<Response, Error> {
+        let mut attempt = 1;
         loop {
-            // eprintln!("\t{}", url);
-            info!(url);
-            let page: Organizations = self.get(url).send().await?.json().await?;
-
-            organizations.extend(page.items);
+            let result = self.get_authenticated(endpoint_url.clone()).await;
+            if attempt >= self.retry.max_attempts {
+                return result;
+            }
 
-            // eprintln!("{:#?}", page._links);
-            match page._links.get("next") {
-                Some(next) => {
-                    url = next.href.clone();
+            let delay = match &result {
+                Ok(response) if retry::is_retryable_status(response.status()) => {
+                    let delay =
+                        retry::retry_after(response).unwrap_or_else(|| self.retry.backoff(attempt));
+                    // Rather fail (with Error::RateLimited for a 429) than
+                    // hang for hours
+                    if delay > self.retry.max_retry_after {
+                        warn!(status = %response.status(), ?delay, "Retry-After too long, giving up");
+                        return result;
+                    }
+                    warn!(attempt, status = %response.status(), ?delay, "retrying request");
+                    delay
                 }
-                _ => {
-                    break;
+                Err(Error::Reqwest(error)) if retry::is_retryable_error(error) => {
+                    let delay = self.retry.backoff(attempt);
+                    warn!(attempt, %error, ?delay, "retrying request");
+                    delay
                 }
-            }
+                _ => return result,
+            };
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
         }
+    }
 
-        Ok(organizations)
+    /// Send a single GET, refreshing the access token and trying again once
+    /// if the API responds with 401 Unauthorized.
+    async fn get_authenticated(&self, endpoint_url: Url) -> Result<Response, Error> {
+        self.send_authenticated(Method::GET, endpoint_url, None)
+            .await
     }
 
-    #[instrument(skip(self))]
-    pub async fn subscriptions(&self) -> Result<Vec<Subscription>, reqwest::Error> {
-        // Really ought to return a Stream/Iterator
-
-        let organizations = self.organizations().await?;
-
-        let mut subscriptions: Vec<Subscription> = vec![];
-
-        for organization in organizations.iter() {
-            let mut url = format!(
-                "https://api.platform.sh/organizations/{}/subscriptions?filter=active",
-                organization.id
-            );
-
-            // eprintln!("Getting subscriptions...");
-            loop {
-                // eprintln!("\t{}", url);
-                let page: Subscriptions = self.get(url).send().await?.json().await?;
-
-                subscriptions.extend(page.items);
-
-                // eprintln!("{:#?}", page._links);
-                match page._links.get("next") {
-                    Some(next) => {
-                        // url = if next.href.starts_with("https://") {
-                        //     next.href.clone()
-                        // } else {
-                        //     format!("https://api.platform.sh{}", next.href)
-                        // }
-                        url = next.href.clone()
-                    }
-                    _ => {
-                        break;
-                    }
-                }
+    async fn send_authenticated(
+        &self,
+        method: Method,
+        endpoint_url: Url,
+        body: Option<&Value>,
+    ) -> Result<Response, Error> {
+        let request = |access_token: &str| {
+            let request = self
+                .client
+                .request(method.clone(), endpoint_url.clone())
+                .bearer_auth(access_token);
+            match body {
+                Some(body) => request.json(body),
+                None => request,
             }
+        };
+
+        let access_token = self.access_token().await?;
+        let response = self.send(request(&access_token)).await?;
+
+        // A rejected token means the request was never acted upon, so it is
+        // safe to send it again - even if it isn't idempotent
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
         }
 
-        Ok(subscriptions)
+        warn!("access token rejected, re-authenticating");
+        let access_token = self.refresh_token(&access_token).await?;
+        self.send(request(&access_token)).await
     }
 
-    #[instrument(skip(self))]
-    pub async fn git_commit(
+    /// Send a POST, PATCH, DELETE, ... to `url` (relative to the API url).
+    /// Unlike [`get`](Self::get) these are never retried on 5xx or connection
+    /// errors, as the API may already have acted on them.
+    #[instrument(skip(self, body))]
+    pub async fn request(
         &self,
-        project_id: &str,
-        head_commit: &str,
-    ) -> Result<GitCommit, reqwest::Error> {
-        // eprintln!("https://api.platform.sh/projects/{}/git/commits/{}", project_id, head_commit);
+        method: Method,
+        url: String,
+        body: Option<&Value>,
+    ) -> Result<Response, Error> {
+        let endpoint_url = endpoint_url(&self.api_url, &url)?;
+
+        debug!(url);
         let response = self
-            .get(format!(
-                "https://api.platform.sh/projects/{}/git/commits/{}",
-                project_id, head_commit
-            ))
-            .send()
+            .send_authenticated(method, endpoint_url.clone(), body)
             .await?;
-        // eprint!("git commit {:#?}", response);
-        let git_commit: GitCommit = response.json().await?;
-        // eprintln!("ok");
 
-        Ok(git_commit)
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            Err(Error::from_response(endpoint_url, response).await)
+        }
     }
 
-    #[instrument(skip(self))]
-    pub async fn git_tree(&self, project_id: &str, tree: &str) -> Result<GitTree, reqwest::Error> {
-        // eprint!("https://api.platform.sh/projects/{}/git/trees/{}", project_id, tree);
-        let git_tree: GitTree = self
-            .get(format!(
-                "https://api.platform.sh/projects/{}/git/trees/{}",
-                project_id, tree
-            ))
-            .send()
+    /// Send a request to an endpoint that starts activities, and return them
+    async fn request_activities(
+        &self,
+        method: Method,
+        url: String,
+        body: Option<Value>,
+    ) -> Result<Vec<Activity>, Error> {
+        let response = self.request(method, url, body.as_ref()).await?;
+        let accepted: AcceptedResponse = decode(response).await?;
+
+        Ok(accepted._embedded.activities)
+    }
+
+    /// Send a request to an endpoint that starts an activity, and return it
+    async fn request_activity(
+        &self,
+        method: Method,
+        url: String,
+        body: Option<Value>,
+    ) -> Result<Activity, Error> {
+        let endpoint_url = endpoint_url(&self.api_url, &url)?;
+
+        self.request_activities(method, url, body)
             .await?
-            .json()
-            .await?;
-        // println!("ok");
+            .into_iter()
+            .next()
+            .ok_or(Error::NoActivity(endpoint_url))
+    }
 
-        Ok(git_tree)
+    /// Send a request through the configured [`Transport`]
+    async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        self.transport.execute(&self.client, request.build()?).await
+    }
+
+    /// Follow the `_links.next` chain of a HAL collection starting at `url`,
+    /// yielding items as each page arrives.
+    pub fn paginate<T: HALPage>(
+        &self,
+        url: String,
+    ) -> impl Stream<Item = Result<T::Item, Error>> + '_ {
+        stream::try_unfold(Some(url), move |next| async move {
+            let Some(url) = next else {
+                return Ok::<_, Error>(None); // previous page was the last one
+            };
+            info!(url);
+            let page: T = self.get_json(url).await?;
+            let (items, next) = page.into_parts();
+
+            Ok(Some((stream::iter(items.into_iter().map(Ok)), next)))
+        })
+        .try_flatten()
+    }
+
+    pub fn organizations_stream(&self) -> impl Stream<Item = Result<Organization, Error>> + '_ {
+        self.paginate::<Organizations>("organizations".to_string())
+    }
+
+    #[instrument(skip(self))]
+    pub async fn organizations(&self) -> Result<Vec<Organization>, Error> {
+        self.organizations_stream().try_collect().await
+    }
+
+    /// Active subscriptions across all organizations
+    pub fn subscriptions_stream(&self) -> impl Stream<Item = Result<Subscription, Error>> + '_ {
+        self.organizations_stream()
+            .map_ok(move |organization| {
+                self.paginate::<Subscriptions>(format!(
+                    "organizations/{}/subscriptions?filter=active",
+                    organization.id
+                ))
+            })
+            .try_flatten()
+    }
+
+    #[instrument(skip(self))]
+    pub async fn subscriptions(&self) -> Result<Vec<Subscription>, Error> {
+        self.subscriptions_stream().try_collect().await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn project(&self, project_id: &str) -> Result<Project, Error> {
+        self.get_json(format!("projects/{}", project_id)).await
+    }
+
+    /// Domains attached to the project, serving its production environment
+    #[instrument(skip(self))]
+    pub async fn domains(&self, project_id: &str) -> Result<Vec<Domain>, Error> {
+        self.get_json(format!("projects/{}/domains", project_id))
+            .await
+    }
+
+    pub fn organization_projects_stream<'a>(
+        &'a self,
+        organization_id: &str,
+    ) -> impl Stream<Item = Result<OrganizationProject, Error>> + 'a {
+        self.paginate::<Projects>(format!("organizations/{}/projects", organization_id))
+    }
+
+    #[instrument(skip(self))]
+    pub async fn organization_projects(
+        &self,
+        organization_id: &str,
+    ) -> Result<Vec<OrganizationProject>, Error> {
+        self.organization_projects_stream(organization_id)
+            .try_collect()
+            .await
+    }
+
+    /// Projects across all organizations
+    pub fn projects_stream(&self) -> impl Stream<Item = Result<OrganizationProject, Error>> + '_ {
+        self.organizations_stream()
+            .map_ok(move |organization| self.organization_projects_stream(&organization.id))
+            .try_flatten()
+    }
+
+    #[instrument(skip(self))]
+    pub async fn projects(&self) -> Result<Vec<OrganizationProject>, Error> {
+        self.projects_stream().try_collect().await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn git_commit(
+        &self,
+        project_id: &str,
+        head_commit: &str,
+    ) -> Result<GitCommit, Error> {
+        self.get_git_object(
+            project_id,
+            "commits",
+            head_commit,
+            format!("projects/{}/git/commits/{}", project_id, head_commit),
+        )
+        .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn git_tree(&self, project_id: &str, tree: &str) -> Result<GitTree, Error> {
+        self.get_git_object(
+            project_id,
+            "trees",
+            tree,
+            format!("projects/{}/git/trees/{}", project_id, tree),
+        )
+        .await
     }
 
     #[async_recursion]
@@ -221,7 +550,7 @@ impl ApiClient {
         f: fn(path: &str) -> bool,
         limit: u8,
         root: String,
-    ) -> Result<Vec<GitSearchResult>, reqwest::Error> {
+    ) -> Result<Vec<GitSearchResult>, Error> {
         let mut results: Vec<GitSearchResult> = Vec::new();
 
         if limit == 0 {
@@ -267,7 +596,7 @@ impl ApiClient {
         project_id: &str,
         tree: &str,
         path: &str,
-    ) -> Result<Option<GitTreeItem>, reqwest::Error> {
+    ) -> Result<Option<GitTreeItem>, Error> {
         let git_tree = self.git_tree(project_id, tree).await?;
 
         let mut result: Option<GitTreeItem> = None;
@@ -281,18 +610,35 @@ impl ApiClient {
     }
 
     #[instrument(skip(self))]
-    pub async fn git_blob(&self, project_id: &str, sha: &str) -> Result<GitBlob, reqwest::Error> {
-        let git_blob: GitBlob = self
-            .get(format!(
-                "https://api.platform.sh/projects/{}/git/blobs/{}",
-                project_id, sha
-            ))
-            .send()
-            .await?
-            .json()
-            .await?;
+    pub async fn git_blob(&self, project_id: &str, sha: &str) -> Result<GitBlob, Error> {
+        self.get_git_object(
+            project_id,
+            "blobs",
+            sha,
+            format!("projects/{}/git/blobs/{}", project_id, sha),
+        )
+        .await
+    }
+
+    /// GET a git object, going through the cache if there is one
+    async fn get_git_object<T: DeserializeOwned + Serialize>(
+        &self,
+        project_id: &str,
+        kind: &str,
+        sha: &str,
+        url: String,
+    ) -> Result<T, Error> {
+        let Some(cache) = &self.cache else {
+            return self.get_json(url).await;
+        };
+
+        if let Some(object) = cache.get(project_id, kind, sha).await {
+            return Ok(object);
+        }
+        let object = self.get_json(url).await?;
+        cache.put(project_id, kind, sha, &object).await;
 
-        Ok(git_blob)
+        Ok(object)
     }
 
     #[instrument(skip(self))]
@@ -301,7 +647,6 @@ impl ApiClient {
         let blob: GitBlob = self.git_blob(project_id, sha).await?;
         // eprintln!("download... ok");
 
-        // Add compound Error type Enum reqwest::Error + base64 error
         let content = general_purpose::STANDARD.decode(blob.content)?;
         // eprintln!("base64 decode... ok");
 
@@ -309,24 +654,174 @@ impl ApiClient {
     }
 
     pub async fn main_environment(&self, project_id: &str) -> Result<Environment, Error> {
-        let environments_res: Result<Vec<Environment>, reqwest::Error> = self
-            .get(format!(
-                "https://api.platform.sh/projects/{}/environments",
-                project_id
-            ))
-            .send()
-            .await?
-            .json()
-            .await;
+        let environments = self.environments(project_id).await?;
 
-        if let Ok(environments) = environments_res {
-            for environment in environments.iter() {
-                if environment.is_main {
-                    return Ok(environment.clone());
-                }
+        for environment in environments.iter() {
+            if environment.is_main {
+                return Ok(environment.clone());
             }
         }
 
-        return Err(Error::NotFound);
+        Err(Error::NotFound)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn environments(&self, project_id: &str) -> Result<Vec<Environment>, Error> {
+        self.get_json(format!("projects/{}/environments", project_id))
+            .await
     }
+
+    #[instrument(skip(self))]
+    pub async fn environment(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+    ) -> Result<Environment, Error> {
+        self.get_json(format!(
+            "projects/{}/environments/{}",
+            project_id, environment_id
+        ))
+        .await
+    }
+
+    /// POST to one of an environment's action endpoints, e.g. `activate`
+    async fn environment_action(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+        action: &str,
+        body: Option<Value>,
+    ) -> Result<Activity, Error> {
+        self.request_activity(
+            Method::POST,
+            format!(
+                "projects/{}/environments/{}/{}",
+                project_id, environment_id, action
+            ),
+            body,
+        )
+        .await
+    }
+
+    /// Create environment `name` as a child of `environment_id`. Unless
+    /// `clone_parent` is set, it starts out without the parent's data.
+    #[instrument(skip(self))]
+    pub async fn branch_environment(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+        name: &str,
+        title: Option<&str>,
+        clone_parent: bool,
+    ) -> Result<Activity, Error> {
+        let body = json!({
+            "name": name,
+            "title": title.unwrap_or(name),
+            "clone_parent": clone_parent,
+        });
+        self.environment_action(project_id, environment_id, "branch", Some(body))
+            .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn activate_environment(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+    ) -> Result<Activity, Error> {
+        self.environment_action(project_id, environment_id, "activate", None)
+            .await
+    }
+
+    /// Deactivate the environment, deleting its data and services
+    #[instrument(skip(self))]
+    pub async fn deactivate_environment(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+    ) -> Result<Activity, Error> {
+        self.environment_action(project_id, environment_id, "deactivate", None)
+            .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn pause_environment(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+    ) -> Result<Activity, Error> {
+        self.environment_action(project_id, environment_id, "pause", None)
+            .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn resume_environment(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+    ) -> Result<Activity, Error> {
+        self.environment_action(project_id, environment_id, "resume", None)
+            .await
+    }
+
+    /// Delete an environment, which has to be deactivated first
+    #[instrument(skip(self))]
+    pub async fn delete_environment(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+    ) -> Result<Activity, Error> {
+        self.request_activity(
+            Method::DELETE,
+            format!("projects/{}/environments/{}", project_id, environment_id),
+            None,
+        )
+        .await
+    }
+
+    /// Merge the environment's code into its parent
+    #[instrument(skip(self))]
+    pub async fn merge_environment(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+    ) -> Result<Activity, Error> {
+        self.environment_action(project_id, environment_id, "merge", None)
+            .await
+    }
+
+    /// Copy code and/or data from the parent environment
+    #[instrument(skip(self))]
+    pub async fn synchronize_environment(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+        code: bool,
+        data: bool,
+    ) -> Result<Activity, Error> {
+        let body = json!({
+            "synchronize_code": code,
+            "synchronize_data": data,
+        });
+        self.environment_action(project_id, environment_id, "synchronize", Some(body))
+            .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn redeploy_environment(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+    ) -> Result<Activity, Error> {
+        self.environment_action(project_id, environment_id, "redeploy", None)
+            .await
+    }
+}
+
+/// Deserialize a JSON response body, keeping the url for the error message
+async fn decode<T: DeserializeOwned>(response: Response) -> Result<T, Error> {
+    let url = response.url().clone();
+    let body = response.bytes().await?;
+
+    serde_json::from_slice(&body).map_err(|source| Error::Deserialize { url, source })
 }
@@ -1,5 +1,5 @@
 use chrono::{DateTime, Local};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 
@@ -17,6 +17,18 @@ pub struct HALLink {
     pub method: Option<String>,
 }
 
+/// One page of a HAL collection - the items plus a `_links.next` href
+/// pointing at the following page, if any.
+pub trait HALPage: DeserializeOwned {
+    type Item;
+
+    fn into_parts(self) -> (Vec<Self::Item>, Option<String>);
+}
+
+fn next_href(links: &HashMap<String, HALLink>) -> Option<String> {
+    links.get("next").map(|next| next.href.clone())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Organization {
     pub id: String,
@@ -36,6 +48,15 @@ pub struct Organizations {
     pub _links: HashMap<String, HALLink>,
 }
 
+impl HALPage for Organizations {
+    type Item = Organization;
+
+    fn into_parts(self) -> (Vec<Organization>, Option<String>) {
+        let next = next_href(&self._links);
+        (self.items, next)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Subscription {
     pub id: String,
@@ -65,8 +86,18 @@ pub struct Subscriptions {
     pub _links: HashMap<String, HALLink>,
 }
 
+impl HALPage for Subscriptions {
+    type Item = Subscription;
+
+    fn into_parts(self) -> (Vec<Subscription>, Option<String>) {
+        let next = next_href(&self._links);
+        (self.items, next)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Project {
+    pub id: String,
     pub created_at: Option<DateTime<Local>>,
     pub updated_at: Option<DateTime<Local>>,
 
@@ -79,14 +110,12 @@ pub struct Project {
     pub organization: String,
     pub default_branch: Option<String>,
 
-    // should be struct { code: ..., message: ... }
-    pub status: HashMap<String, String>,
+    pub status: ProjectStatus,
 
     pub timezone: String,
     pub region: String,
 
-    // should be struct { url: ..., client_ssh_key: ... }
-    pub repository: HashMap<String, String>,
+    pub repository: ProjectRepository,
 
     pub default_domain: Option<String>,
 
@@ -94,13 +123,52 @@ pub struct Project {
     pub _links: HashMap<String, HALLink>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectStatus {
+    pub code: String, // "provisioning" "provisioned" "suspended" ...
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectRepository {
+    pub url: String, // git remote
+    pub client_ssh_key: String,
+}
+
+/// A project as listed by `/organizations/{id}/projects` - a summary, use
+/// `ApiClient::project()` for the full [`Project`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrganizationProject {
+    pub id: String,
+    pub organization_id: String,
+    pub subscription_id: String,
+    pub title: String,
+    pub region: String,
+    pub plan: Option<String>,
+    pub timezone: Option<String>,
+    pub default_branch: Option<String>,
+    pub status: String,             // "requested" "active" "suspended" "deleted"
+    pub project_ui: Option<String>, // URL
+    pub created_at: Option<DateTime<Local>>,
+    pub updated_at: Option<DateTime<Local>>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Projects {
     pub count: i32,
-    pub items: Vec<Project>,
+    pub items: Vec<OrganizationProject>,
     pub _links: HashMap<String, HALLink>,
 }
 
+impl HALPage for Projects {
+    type Item = OrganizationProject;
+
+    fn into_parts(self) -> (Vec<OrganizationProject>, Option<String>) {
+        let next = next_href(&self._links);
+        (self.items, next)
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Environment {
     pub created_at: Option<DateTime<Local>>, // date-time
@@ -147,13 +215,222 @@ pub struct PlatformAppCron {
     pub commands: Option<PlatformAppCronCommands>,
 }
 #[derive(Debug, Serialize, Deserialize)]
+pub struct PlatformAppSourceOperation {
+    pub command: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlatformAppSource {
+    /// Directory of the app's code, relative to the repository root
+    pub root: Option<String>,
+    pub operations: Option<HashMap<String, PlatformAppSourceOperation>>,
+}
+
+/// `service:endpoint`, or the same spelled out. Upsun also accepts an empty
+/// value, meaning the service named like the relationship.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PlatformAppRelationship {
+    Short(String),
+    Long {
+        service: Option<String>,
+        endpoint: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PlatformAppMount {
+    /// Pre 2019 `shared:files/path` form
+    Legacy(String),
+    Mount {
+        /// "local", "service", "tmp", "storage", "instance"
+        source: String,
+        source_path: Option<String>,
+        /// Network storage service, for "service" mounts
+        service: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlatformAppWebLocation {
+    pub root: Option<String>,
+    /// `true`, `false` or the script to pass requests to
+    pub passthru: Option<Value>,
+    pub index: Option<Vec<String>>,
+    /// A duration like "1h", or -1
+    pub expires: Option<Value>,
+    pub allow: Option<bool>,
+    pub scripts: Option<bool>,
+    pub headers: Option<HashMap<String, String>>,
+    pub rules: Option<HashMap<String, Value>>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlatformAppWebCommands {
+    pub start: Option<String>,
+    pub pre_start: Option<String>,
+    pub post_start: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlatformAppWeb {
+    pub locations: Option<HashMap<String, PlatformAppWebLocation>>,
+    pub commands: Option<PlatformAppWebCommands>,
+    pub upstream: Option<Value>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PlatformAppExtension {
+    Name(String),
+    Configured {
+        name: String,
+        configuration: Option<Value>,
+    },
+}
+
+impl PlatformAppExtension {
+    pub fn name(&self) -> &str {
+        match self {
+            PlatformAppExtension::Name(name) => name,
+            PlatformAppExtension::Configured { name, .. } => name,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlatformAppRuntime {
+    /// PHP extensions
+    pub extensions: Option<Vec<PlatformAppExtension>>,
+    pub disabled_extensions: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlatformAppWorkerCommands {
+    pub start: String,
+    pub stop: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlatformAppWorker {
+    pub commands: Option<PlatformAppWorkerCommands>,
+    pub size: Option<String>,
+    pub disk: Option<i32>,
+    pub mounts: Option<HashMap<String, PlatformAppMount>>,
+    pub relationships: Option<HashMap<String, Option<PlatformAppRelationship>>>,
+    pub variables: Option<HashMap<String, HashMap<String, Value>>>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// An app, as in `.platform.app.yaml`. Keys not modelled here end up in
+/// `extra`, so new or rare ones don't make the whole file unreadable.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PlatformApp {
     pub name: String,
     #[serde(rename = "type")]
     pub r#type: String,
+    pub size: Option<String>,
+    pub source: Option<PlatformAppSource>,
+    pub relationships: Option<HashMap<String, Option<PlatformAppRelationship>>>,
+    /// MB
+    pub disk: Option<i32>,
+    pub mounts: Option<HashMap<String, PlatformAppMount>>,
+    pub web: Option<PlatformAppWeb>,
+    pub runtime: Option<PlatformAppRuntime>,
+    /// Global packages per package manager, e.g. `php: {composer/composer: "^2"}`.
+    /// Versions are values as an unquoted `2.0` is a number.
+    pub dependencies: Option<HashMap<String, HashMap<String, Value>>>,
+    /// Per prefix, e.g. `env: {FOO: bar}` or `php: {memory_limit: 256M}`
+    pub variables: Option<HashMap<String, HashMap<String, Value>>>,
     pub build: Option<HashMap<String, String>>,
     pub hooks: Option<HashMap<String, String>>,
     pub crons: Option<HashMap<String, PlatformAppCron>>,
+    pub workers: Option<HashMap<String, PlatformAppWorker>>,
+    /// Role per user type, e.g. `ssh: contributor`
+    pub access: Option<HashMap<String, String>>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteCache {
+    pub enabled: bool,
+    pub default_ttl: Option<i64>,
+    pub cookies: Option<Vec<String>>,
+    pub headers: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteSsi {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteRedirectPath {
+    pub to: String,
+    pub code: Option<u16>,
+    pub prefix: Option<bool>,
+    pub append_suffix: Option<bool>,
+    pub regexp: Option<bool>,
+}
+
+/// Partial redirects within an upstream route
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteRedirects {
+    pub expires: Option<Value>,
+    pub paths: Option<HashMap<String, RouteRedirectPath>>,
+}
+
+/// A route from routes.yaml, keyed by a url pattern like `https://www.{default}/`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Route {
+    /// "upstream" or "redirect"
+    #[serde(rename = "type")]
+    pub r#type: String,
+    /// `app:http`, for upstream routes
+    pub upstream: Option<String>,
+    /// Target url pattern, for redirect routes
+    pub to: Option<String>,
+    pub redirects: Option<RouteRedirects>,
+    pub cache: Option<RouteCache>,
+    pub ssi: Option<RouteSsi>,
+    pub id: Option<String>,
+    pub primary: Option<bool>,
+    pub attributes: Option<HashMap<String, String>>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl Route {
+    pub fn is_redirect(&self) -> bool {
+        self.r#type == "redirect"
+    }
+
+    /// Name of the app an upstream route sends requests to
+    pub fn app(&self) -> Option<&str> {
+        self.upstream
+            .as_deref()
+            .map(|upstream| upstream.split_once(':').map_or(upstream, |(app, _)| app))
+    }
+}
+
+/// A domain attached to a project
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Domain {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub is_default: bool,
+    pub created_at: Option<DateTime<Local>>,
+    pub updated_at: Option<DateTime<Local>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -203,16 +480,60 @@ pub struct Activity {
     pub updated_at: Option<DateTime<Local>>,
     #[serde(rename = "type")]
     pub r#type: String,
+    #[serde(default)]
     pub parameters: HashMap<String, Value>,
-    pub state: String,
-    pub result: Option<String>,
+    #[serde(default)]
+    pub project: String,
+    #[serde(default)]
+    pub environments: Vec<String>,
+    pub state: String, // enum "pending" "in_progress" "complete" "cancelled" "staged"
+    pub result: Option<String>, // enum "success" "failure"
     pub started_at: Option<DateTime<Local>>,
     pub completed_at: Option<DateTime<Local>>,
     pub cancelled_at: Option<DateTime<Local>>,
+    pub completion_percent: Option<i32>,
+    #[serde(default)]
     pub timings: HashMap<String, f64>,
+    pub description: Option<String>,
+    pub text: Option<String>,
     pub _links: HashMap<String, HALLink>,
 }
 
+impl Activity {
+    /// Complete or cancelled - the state won't change anymore
+    pub fn is_finished(&self) -> bool {
+        self.state == "complete" || self.state == "cancelled"
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.state == "complete" && self.result.as_deref() == Some("success")
+    }
+}
+
+/// Response to a request that started one or more activities, e.g.
+/// redeploying an environment
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AcceptedResponse {
+    pub status: String,
+    pub code: u16,
+    #[serde(default)]
+    pub _embedded: AcceptedActivities,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AcceptedActivities {
+    pub activities: Vec<Activity>,
+}
+
+/// A line of an activity's log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityLogRecord {
+    pub timestamp: Option<DateTime<Local>>,
+    pub message: String,
+    /// Set on the final record - the log is complete and won't grow anymore
+    pub seal: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct User {
     pub id: String, // UUID
@@ -0,0 +1,255 @@
+use reqwest::{header, Client, Request, Response, ResponseBuilderExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::Error;
+
+/// Keys scrubbed from recorded response bodies
+const SECRETS: [&str; 4] = ["access_token", "refresh_token", "id_token", "api_token"];
+
+/// Response headers kept in fixture files
+const HEADERS: [header::HeaderName; 2] = [header::CONTENT_TYPE, header::RETRY_AFTER];
+
+/// How requests reach the API.
+///
+/// Besides the network, responses can be recorded to, and replayed from, a
+/// directory of fixture files - one JSON file per method, url and JSON body -
+/// so tests
+/// can run against real API responses without network access.
+#[derive(Debug, Clone, Default)]
+pub enum Transport {
+    #[default]
+    Network,
+    /// Send requests and save every response as a fixture, with tokens scrubbed
+    Record(PathBuf),
+    /// Answer requests from fixtures, never touching the network
+    Replay(PathBuf),
+}
+
+/// A recorded response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Fixture {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// JSON bodies are stored as JSON to keep fixtures readable, anything
+    /// else as a string
+    pub body: Value,
+}
+
+impl Transport {
+    pub(crate) async fn execute(
+        &self,
+        client: &Client,
+        request: Request,
+    ) -> Result<Response, Error> {
+        match self {
+            Transport::Network => Ok(client.execute(request).await?),
+            Transport::Record(dir) => {
+                let path = dir.join(fixture_name(&request));
+                let response = client.execute(request).await?;
+                record(&path, response).await
+            }
+            Transport::Replay(dir) => {
+                let path = dir.join(fixture_name(&request));
+                debug!(path = %path.display(), "replaying");
+                replay(&path, request.url().clone())
+            }
+        }
+    }
+}
+
+/// File name for a request, e.g. `GET_projects_abc123_environments.json`.
+/// Requests with a JSON body get a hash of it appended, so that writes to the
+/// same endpoint don't share a fixture. Form bodies are left out, the token
+/// exchange would tie fixtures to the API token otherwise.
+pub(crate) fn fixture_name(request: &Request) -> String {
+    let url = request.url();
+    let mut name = format!(
+        "{} {}",
+        request.method(),
+        url.path().trim_start_matches('/')
+    );
+    if let Some(query) = url.query() {
+        name.push('?');
+        name.push_str(query);
+    }
+
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let name = name.trim_end_matches('_');
+    match json_body(request) {
+        Some(body) => format!("{}-{:016x}.json", name, fnv1a(body)),
+        None => format!("{}.json", name),
+    }
+}
+
+fn json_body(request: &Request) -> Option<&[u8]> {
+    let content_type = request.headers().get(header::CONTENT_TYPE)?;
+    if !content_type.as_bytes().starts_with(b"application/json") {
+        return None;
+    }
+    request.body()?.as_bytes()
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` is guaranteed to stay the same
+/// across Rust releases, and so keeps naming the same fixture files
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+async fn record(path: &Path, response: Response) -> Result<Response, Error> {
+    let url = response.url().clone();
+    let status = response.status();
+    let mut headers = BTreeMap::new();
+    for name in HEADERS.iter() {
+        if let Some(value) = response.headers().get(name).and_then(|v| v.to_str().ok()) {
+            headers.insert(name.to_string(), value.to_string());
+        }
+    }
+    let body = response.bytes().await?;
+
+    let fixture = Fixture {
+        status: status.as_u16(),
+        headers,
+        body: match serde_json::from_slice(&body) {
+            Ok(mut json) => {
+                scrub(&mut json);
+                json
+            }
+            Err(_) => Value::String(String::from_utf8_lossy(&body).to_string()),
+        },
+    };
+
+    debug!(path = %path.display(), "recording");
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, serde_json::to_vec_pretty(&fixture).unwrap()).await?;
+
+    // Hand back the scrubbed body as well, so recording and replaying behave
+    // the same
+    fixture.into_response(url)
+}
+
+fn replay(path: &Path, url: url::Url) -> Result<Response, Error> {
+    let buffer = std::fs::read(path).map_err(|_| Error::MissingFixture(path.to_path_buf()))?;
+    let fixture: Fixture =
+        serde_json::from_slice(&buffer).map_err(|source| Error::Deserialize {
+            url: url.clone(),
+            source,
+        })?;
+
+    fixture.into_response(url)
+}
+
+impl Fixture {
+    fn into_response(self, url: url::Url) -> Result<Response, Error> {
+        let mut builder = http::Response::builder().status(self.status).url(url);
+        for (name, value) in self.headers.iter() {
+            builder = builder.header(name, value);
+        }
+        let body = match self.body {
+            Value::String(body) => body,
+            json => json.to_string(),
+        };
+
+        Ok(builder
+            .body(body)
+            .expect("fixture status and headers are valid")
+            .into())
+    }
+}
+
+/// Replace the value of every secret looking key, however deeply nested
+fn scrub(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRETS.contains(&key.as_str()) {
+                    *value = Value::String("REDACTED".to_string());
+                } else {
+                    scrub(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(scrub),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn scrubs_nested_tokens() {
+        let mut value = json!({
+            "access_token": "secret",
+            "expires_in": 900,
+            "items": [{"api_token": "secret", "name": "kept"}],
+        });
+        scrub(&mut value);
+
+        assert_eq!(
+            value,
+            json!({
+                "access_token": "REDACTED",
+                "expires_in": 900,
+                "items": [{"api_token": "REDACTED", "name": "kept"}],
+            })
+        );
+    }
+
+    #[test]
+    fn fixture_names() {
+        let client = Client::new();
+        let request = client
+            .get("https://api.platform.sh/organizations/abc/subscriptions?filter=active")
+            .build()
+            .unwrap();
+        assert_eq!(
+            fixture_name(&request),
+            "GET_organizations_abc_subscriptions_filter_active.json"
+        );
+
+        let request = client
+            .post("https://auth.api.platform.sh/oauth2/token")
+            .form(&[("grant_type", "api_token"), ("api_token", "secret")])
+            .build()
+            .unwrap();
+        assert_eq!(fixture_name(&request), "POST_oauth2_token.json");
+    }
+
+    #[test]
+    fn fixture_names_tell_bodies_apart() {
+        let client = Client::new();
+        let create = |name: &str| {
+            let request = client
+                .post("https://api.platform.sh/projects/abc/variables")
+                .json(&json!({"name": name, "value": "x"}))
+                .build()
+                .unwrap();
+            fixture_name(&request)
+        };
+
+        assert!(create("A").starts_with("POST_projects_abc_variables-"));
+        assert_eq!(create("A"), create("A"));
+        assert_ne!(create("A"), create("B"));
+    }
+}
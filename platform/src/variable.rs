@@ -0,0 +1,189 @@
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{Activity, ApiClient, EnvironmentVariable, Error, Variable};
+
+/// Characters escaped in variable names, as in PHP's `rawurlencode()` which
+/// the Platform.sh CLI uses - `env:FOO` becomes `env%3AFOO`
+const NAME: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// A variable to create, or the new state of one to update
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VariableRequest {
+    pub name: String,
+    pub value: String,
+    pub is_json: bool,
+    pub is_sensitive: bool,
+    pub visible_build: bool,
+    pub visible_runtime: bool,
+    /// Environment variables only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_enabled: Option<bool>,
+    /// Environment variables only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_inheritable: Option<bool>,
+}
+
+impl VariableRequest {
+    /// A plain variable, visible during build and at runtime
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> VariableRequest {
+        VariableRequest {
+            name: name.into(),
+            value: value.into(),
+            is_json: false,
+            is_sensitive: false,
+            visible_build: true,
+            visible_runtime: true,
+            is_enabled: None,
+            is_inheritable: None,
+        }
+    }
+
+    /// Body for PATCH, which can't rename a variable
+    fn update_body(&self) -> serde_json::Value {
+        let mut body = serde_json::to_value(self).expect("VariableRequest serializes");
+        if let Some(body) = body.as_object_mut() {
+            body.remove("name");
+        }
+        body
+    }
+}
+
+fn encode(name: &str) -> String {
+    utf8_percent_encode(name, NAME).to_string()
+}
+
+impl ApiClient {
+    #[instrument(skip(self))]
+    pub async fn project_variables(&self, project_id: &str) -> Result<Vec<Variable>, Error> {
+        self.get_json(format!("projects/{}/variables", project_id))
+            .await
+    }
+
+    /// Variables of an environment, including the ones inherited from its
+    /// parents and the project
+    #[instrument(skip(self))]
+    pub async fn environment_variables(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+    ) -> Result<Vec<EnvironmentVariable>, Error> {
+        self.get_json(format!(
+            "projects/{}/environments/{}/variables",
+            project_id, environment_id
+        ))
+        .await
+    }
+
+    /// Create a project variable. The returned activities, if any, redeploy
+    /// the environments that pick it up.
+    #[instrument(skip(self, variable), fields(name = variable.name))]
+    pub async fn create_project_variable(
+        &self,
+        project_id: &str,
+        variable: &VariableRequest,
+    ) -> Result<Vec<Activity>, Error> {
+        self.request_activities(
+            Method::POST,
+            format!("projects/{}/variables", project_id),
+            Some(serde_json::to_value(variable).expect("VariableRequest serializes")),
+        )
+        .await
+    }
+
+    #[instrument(skip(self, variable), fields(name = variable.name))]
+    pub async fn update_project_variable(
+        &self,
+        project_id: &str,
+        variable: &VariableRequest,
+    ) -> Result<Vec<Activity>, Error> {
+        self.request_activities(
+            Method::PATCH,
+            format!(
+                "projects/{}/variables/{}",
+                project_id,
+                encode(&variable.name)
+            ),
+            Some(variable.update_body()),
+        )
+        .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn delete_project_variable(
+        &self,
+        project_id: &str,
+        name: &str,
+    ) -> Result<Vec<Activity>, Error> {
+        self.request_activities(
+            Method::DELETE,
+            format!("projects/{}/variables/{}", project_id, encode(name)),
+            None,
+        )
+        .await
+    }
+
+    #[instrument(skip(self, variable), fields(name = variable.name))]
+    pub async fn create_environment_variable(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+        variable: &VariableRequest,
+    ) -> Result<Vec<Activity>, Error> {
+        self.request_activities(
+            Method::POST,
+            format!(
+                "projects/{}/environments/{}/variables",
+                project_id, environment_id
+            ),
+            Some(serde_json::to_value(variable).expect("VariableRequest serializes")),
+        )
+        .await
+    }
+
+    #[instrument(skip(self, variable), fields(name = variable.name))]
+    pub async fn update_environment_variable(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+        variable: &VariableRequest,
+    ) -> Result<Vec<Activity>, Error> {
+        self.request_activities(
+            Method::PATCH,
+            format!(
+                "projects/{}/environments/{}/variables/{}",
+                project_id,
+                environment_id,
+                encode(&variable.name)
+            ),
+            Some(variable.update_body()),
+        )
+        .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn delete_environment_variable(
+        &self,
+        project_id: &str,
+        environment_id: &str,
+        name: &str,
+    ) -> Result<Vec<Activity>, Error> {
+        self.request_activities(
+            Method::DELETE,
+            format!(
+                "projects/{}/environments/{}/variables/{}",
+                project_id,
+                environment_id,
+                encode(name)
+            ),
+            None,
+        )
+        .await
+    }
+}
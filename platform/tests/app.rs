@@ -0,0 +1,187 @@
+use platform::{PlatformApp, PlatformAppMount, PlatformAppRelationship};
+
+const DRUPAL: &str = r#"
+name: drupal
+type: php:8.3
+size: L
+relationships:
+  database: "db:mysql"
+  redis:
+    service: cache
+    endpoint: redis
+  search:
+disk: 2048
+mounts:
+  web/sites/default/files:
+    source: storage
+    source_path: files
+  /private: "shared:files/private"
+  /tmp:
+    source: tmp
+web:
+  locations:
+    "/":
+      root: web
+      passthru: "/index.php"
+      expires: 5m
+      allow: false
+      rules:
+        '\.(jpe?g|png|gif)$':
+          allow: true
+    "/sites/default/files":
+      root: web/sites/default/files
+      passthru: true
+      expires: -1
+      scripts: false
+      request_buffering:
+        enabled: false
+runtime:
+  extensions:
+    - redis
+    - sodium
+    - name: blackfire
+      configuration:
+        server_id: abc
+  disabled_extensions:
+    - sqlite3
+  xdebug:
+    idekey: PHPSTORM
+dependencies:
+  php:
+    composer/composer: "^2"
+    drush/drush: 8.4
+  nodejs:
+    yarn: "*"
+variables:
+  env:
+    N_PREFIX: /app/.global
+  php:
+    memory_limit: 256M
+    opcache.enable: 1
+  d8settings:
+    skip_permissions_hardening: true
+build:
+  flavor: none
+hooks:
+  build: |
+    set -e
+    composer install
+  deploy: drush -y deploy
+crons:
+  drupal:
+    spec: "*/19 * * * *"
+    commands:
+      start: drush core-cron
+workers:
+  queue:
+    size: S
+    commands:
+      start: drush queue:run mail --time-limit=3600
+    mounts:
+      /tmp:
+        source: tmp
+    variables:
+      env:
+        WORKER: "1"
+    some_new_key: 42
+source:
+  root: /
+  operations:
+    update:
+      command: composer update
+access:
+  ssh: contributor
+firewall:
+  outbound:
+    - ips: ["0.0.0.0/0"]
+      ports: [25]
+additional_hosts:
+  api.example.com: 192.0.2.10
+"#;
+
+#[test]
+fn parses_a_complete_app() {
+    let app: PlatformApp = serde_yaml::from_str(DRUPAL).unwrap();
+
+    assert_eq!(app.name, "drupal");
+    assert_eq!(app.size.as_deref(), Some("L"));
+    assert_eq!(app.disk, Some(2048));
+
+    let relationships = app.relationships.as_ref().unwrap();
+    assert!(matches!(
+        &relationships["database"],
+        Some(PlatformAppRelationship::Short(s)) if s == "db:mysql"
+    ));
+    assert!(matches!(
+        &relationships["redis"],
+        Some(PlatformAppRelationship::Long { service: Some(s), endpoint: Some(e) })
+            if s == "cache" && e == "redis"
+    ));
+    assert!(relationships["search"].is_none());
+
+    let mounts = app.mounts.as_ref().unwrap();
+    assert!(matches!(
+        &mounts["web/sites/default/files"],
+        PlatformAppMount::Mount { source, source_path: Some(path), .. }
+            if source == "storage" && path == "files"
+    ));
+    assert!(
+        matches!(&mounts["/private"], PlatformAppMount::Legacy(s) if s == "shared:files/private")
+    );
+
+    let locations = app.web.as_ref().unwrap().locations.as_ref().unwrap();
+    assert_eq!(locations["/"].root.as_deref(), Some("web"));
+    assert_eq!(locations["/"].passthru, Some("/index.php".into()));
+    assert_eq!(locations["/sites/default/files"].expires, Some((-1).into()));
+    assert!(locations["/sites/default/files"]
+        .extra
+        .contains_key("request_buffering"));
+
+    let runtime = app.runtime.as_ref().unwrap();
+    let extensions: Vec<&str> = runtime
+        .extensions
+        .iter()
+        .flatten()
+        .map(|e| e.name())
+        .collect();
+    assert_eq!(extensions, ["redis", "sodium", "blackfire"]);
+    assert!(runtime.extra.contains_key("xdebug"));
+
+    let dependencies = app.dependencies.as_ref().unwrap();
+    assert_eq!(dependencies["php"]["composer/composer"], "^2");
+    assert_eq!(dependencies["php"]["drush/drush"], 8.4);
+
+    let variables = app.variables.as_ref().unwrap();
+    assert_eq!(variables["php"]["memory_limit"], "256M");
+    assert_eq!(variables["d8settings"]["skip_permissions_hardening"], true);
+
+    let workers = app.workers.as_ref().unwrap();
+    let queue = &workers["queue"];
+    assert_eq!(queue.size.as_deref(), Some("S"));
+    assert_eq!(
+        queue.commands.as_ref().unwrap().start,
+        "drush queue:run mail --time-limit=3600"
+    );
+    assert!(queue.mounts.as_ref().unwrap().contains_key("/tmp"));
+    assert_eq!(queue.extra["some_new_key"], 42);
+
+    let source = app.source.as_ref().unwrap();
+    assert_eq!(
+        source.operations.as_ref().unwrap()["update"].command,
+        "composer update"
+    );
+    assert_eq!(app.access.as_ref().unwrap()["ssh"], "contributor");
+
+    // Not modelled, but kept
+    let mut extra: Vec<&String> = app.extra.keys().collect();
+    extra.sort();
+    assert_eq!(extra, ["additional_hosts", "firewall"]);
+}
+
+#[test]
+fn parses_a_minimal_app() {
+    let app: PlatformApp = serde_yaml::from_str("name: app\ntype: nodejs:20\n").unwrap();
+    assert_eq!(app.r#type, "nodejs:20");
+    assert!(app.web.is_none());
+    assert!(app.extra.is_empty());
+}
@@ -0,0 +1,765 @@
+use futures::TryStreamExt;
+use platform::{ActivityFilter, ApiClient, RetryPolicy, VariableRequest};
+use platform_mock::{MockServer, Seed};
+use std::time::Duration;
+
+const SEED: &str = r#"
+page_size: 2
+organizations:
+  - id: org-adapt
+    name: adapt
+    subscriptions:
+      - { project_id: project1, title: One }
+      - { project_id: project2, title: Two }
+      - { project_id: project3, title: Three }
+  - id: org-clients
+    name: adapt-clients
+    subscriptions:
+      - { project_id: project4, title: Four }
+projects:
+  project1:
+    environments:
+      - { name: main, is_main: true }
+      - { name: staging, parent: main }
+    files:
+      .platform.app.yaml: "name: app\ntype: php:8.3\n"
+      .platform/services.yaml: "db:\n  type: mariadb:10.11\n"
+    activities:
+      - { id: push1, type: environment.push, environments: [main], created_at: "2024-05-01T10:00:00Z" }
+      - { id: push2, type: environment.push, environments: [main], result: failure, created_at: "2024-05-02T10:00:00Z" }
+      - { id: backup1, type: environment.backup, environments: [main], created_at: "2024-05-03T10:00:00Z" }
+      - { id: push3, type: environment.push, environments: [staging], created_at: "2024-05-03T10:00:00Z" }
+      - { id: push4, type: environment.push, environments: [main], created_at: "2024-05-03T10:00:00Z" }
+      - { id: deploy1, type: environment.redeploy, environments: [main], state: complete, pending_polls: 2, created_at: "2024-05-04T10:00:00Z" }
+      - id: deploy2
+        type: environment.redeploy
+        environments: [main]
+        created_at: "2024-05-04T10:30:00Z"
+        log: ["Building application 'app'", "  Installing dependencies ✓", "Deploying"]
+        log_interrupt_after: 1
+      - { id: stuck1, type: environment.redeploy, environments: [staging], state: in_progress, result: null, created_at: "2024-05-04T11:00:00Z" }
+"#;
+
+async fn start(yaml: &str) -> MockServer {
+    MockServer::start(Seed::from_yaml(yaml).unwrap()).await
+}
+
+async fn client(server: &MockServer) -> ApiClient {
+    ApiClient::builder()
+        .api_url(server.url())
+        .auth_url(server.url())
+        .retry_policy(RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(10),
+            ..Default::default()
+        })
+        .build("api-token")
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn pages_through_subscriptions() {
+    let server = start(SEED).await;
+    let client = client(&server).await;
+
+    let subscriptions = client.subscriptions().await.unwrap();
+    let titles: Vec<&str> = subscriptions
+        .iter()
+        .map(|s| s.project_title.as_str())
+        .collect();
+    assert_eq!(titles, vec!["One", "Two", "Three", "Four"]);
+    assert!(server
+        .requests()
+        .contains(&"GET /organizations/org-adapt/subscriptions?filter=active&page=2".to_string()));
+}
+
+#[tokio::test]
+async fn follows_links_behind_a_path_prefix() {
+    let server = start(&format!("path_prefix: /platform\n{}", SEED)).await;
+    let client = client(&server).await;
+    assert!(server.url().ends_with("/platform"));
+
+    // Links from the API point at its root, not at the proxy's prefix
+    let subscriptions = client.subscriptions().await.unwrap();
+    assert_eq!(subscriptions.len(), 4);
+    assert!(server
+        .requests()
+        .contains(&"GET /organizations/org-adapt/subscriptions?filter=active&page=2".to_string()));
+
+    let records: Vec<platform::ActivityLogRecord> = client
+        .activity_log_stream("project1", "deploy2")
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(records.len(), 4);
+}
+
+#[tokio::test]
+async fn walks_git_tree() {
+    let server = start(SEED).await;
+    let client = client(&server).await;
+
+    let environment = client.main_environment("project1").await.unwrap();
+    let commit = client
+        .git_commit("project1", environment.head_commit.as_deref().unwrap())
+        .await
+        .unwrap();
+    let dot_platform = client
+        .git_tree_lookup_path("project1", &commit.tree, ".platform")
+        .await
+        .unwrap()
+        .unwrap();
+    let services = client
+        .git_tree_lookup_path("project1", &dot_platform.sha, "services.yaml")
+        .await
+        .unwrap()
+        .unwrap();
+    let content = client
+        .git_blob_decode("project1", &services.sha)
+        .await
+        .unwrap();
+    assert_eq!(content, b"db:\n  type: mariadb:10.11\n");
+}
+
+#[tokio::test]
+async fn refreshes_token_after_unauthorized() {
+    let yaml = format!(
+        "{}faults:\n  - {{ path: /organizations, status: 401, times: 1 }}\n",
+        SEED
+    );
+    let server = start(&yaml).await;
+    let client = client(&server).await;
+
+    assert_eq!(client.organizations().await.unwrap().len(), 2);
+    assert_eq!(server.tokens_issued(), 2);
+}
+
+#[tokio::test]
+async fn reuses_short_lived_tokens() {
+    let yaml = format!("token_expires_in: 30\n{}", SEED);
+    let server = start(&yaml).await;
+    let client = client(&server).await;
+
+    assert_eq!(client.organizations().await.unwrap().len(), 2);
+    assert_eq!(server.tokens_issued(), 1);
+}
+
+#[tokio::test]
+async fn retries_rate_limited_requests() {
+    let yaml = format!(
+        "{}faults:\n  - {{ path: /organizations, status: 429, retry_after: 0, times: 2 }}\n",
+        SEED
+    );
+    let server = start(&yaml).await;
+    let client = client(&server).await;
+
+    assert_eq!(client.organizations().await.unwrap().len(), 2);
+    let attempts = server
+        .requests()
+        .iter()
+        .filter(|r| r.starts_with("GET /organizations?") || *r == "GET /organizations")
+        .count();
+    assert_eq!(attempts, 3);
+}
+
+#[tokio::test]
+async fn honours_retry_after_past_max_backoff() {
+    let yaml = format!(
+        "{}faults:\n  - {{ path: /organizations, status: 429, retry_after: 1, times: 1 }}\n",
+        SEED
+    );
+    let server = start(&yaml).await;
+    let client = client(&server).await;
+
+    let started = std::time::Instant::now();
+    assert_eq!(client.organizations().await.unwrap().len(), 2);
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn fails_when_asked_to_wait_too_long() {
+    let yaml = format!(
+        "{}faults:\n  - {{ path: /organizations, status: 429, retry_after: 86400, times: 1 }}\n",
+        SEED
+    );
+    let server = start(&yaml).await;
+    let client = client(&server).await;
+
+    let started = std::time::Instant::now();
+    let error = client.organizations().await.unwrap_err();
+    assert!(
+        matches!(error, platform::Error::RateLimited { .. }),
+        "{:?}",
+        error
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(
+        server
+            .requests()
+            .iter()
+            .filter(|r| r.starts_with("GET /organizations"))
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn gives_up_when_unavailable() {
+    let yaml = format!(
+        "{}faults:\n  - {{ path: /projects/project1/environments, status: 503 }}\n",
+        SEED
+    );
+    let server = start(&yaml).await;
+    let client = client(&server).await;
+
+    let error = client.main_environment("project1").await.unwrap_err();
+    assert!(error.is_unavailable());
+}
+
+#[tokio::test]
+async fn lists_and_gets_projects() {
+    let server = start(SEED).await;
+    let client = client(&server).await;
+
+    let projects = client.projects().await.unwrap();
+    let ids: Vec<&str> = projects.iter().map(|p| p.id.as_str()).collect();
+    assert_eq!(ids, vec!["project1", "project2", "project3", "project4"]);
+    assert_eq!(projects[3].organization_id, "org-clients");
+    assert_eq!(projects[0].default_branch.as_deref(), Some("main"));
+
+    let project = client.project("project1").await.unwrap();
+    assert_eq!(project.title, "One");
+    assert_eq!(project.status.code, "provisioned");
+    assert!(project.repository.url.ends_with(":project1.git"));
+
+    let error = client.project("nope").await.unwrap_err();
+    assert!(error.is_not_found());
+}
+
+#[tokio::test]
+async fn environment_lifecycle() {
+    let server = start(SEED).await;
+    let client = client(&server).await;
+
+    let activity = client
+        .branch_environment("project1", "main", "feature", None, false)
+        .await
+        .unwrap();
+    assert_eq!(activity.r#type, "environment.branch");
+    assert_eq!(activity.state, "complete");
+    assert_eq!(activity.result.as_deref(), Some("success"));
+
+    let environment = client.environment("project1", "feature").await.unwrap();
+    assert_eq!(environment.parent.as_deref(), Some("main"));
+    assert_eq!(client.environments("project1").await.unwrap().len(), 3);
+
+    client
+        .pause_environment("project1", "feature")
+        .await
+        .unwrap();
+    client
+        .resume_environment("project1", "feature")
+        .await
+        .unwrap();
+    client
+        .synchronize_environment("project1", "feature", true, false)
+        .await
+        .unwrap();
+    client
+        .merge_environment("project1", "feature")
+        .await
+        .unwrap();
+    client
+        .redeploy_environment("project1", "feature")
+        .await
+        .unwrap();
+
+    // Only inactive environments can be deleted
+    let error = client
+        .delete_environment("project1", "feature")
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(reqwest::StatusCode::BAD_REQUEST));
+
+    client
+        .deactivate_environment("project1", "feature")
+        .await
+        .unwrap();
+    let activity = client
+        .delete_environment("project1", "feature")
+        .await
+        .unwrap();
+    assert_eq!(activity.r#type, "environment.delete");
+    assert!(client
+        .environment("project1", "feature")
+        .await
+        .unwrap_err()
+        .is_not_found());
+}
+
+#[tokio::test]
+async fn does_not_retry_environment_operations() {
+    let yaml = format!(
+        "{}faults:\n  - {{ path: /projects/project1/environments/main/redeploy, status: 503 }}\n",
+        SEED
+    );
+    let server = start(&yaml).await;
+    let client = client(&server).await;
+
+    let error = client
+        .redeploy_environment("project1", "main")
+        .await
+        .unwrap_err();
+    assert!(error.is_unavailable());
+    let attempts = server
+        .requests()
+        .iter()
+        .filter(|r| r.ends_with("/redeploy"))
+        .count();
+    assert_eq!(attempts, 1);
+}
+
+#[tokio::test]
+async fn pages_through_activities() {
+    let server = start(SEED).await;
+    let client = client(&server).await;
+
+    let filter = ActivityFilter {
+        page_size: Some(2),
+        ..Default::default()
+    };
+    let activities = client.activities("project1", None, &filter).await.unwrap();
+    let ids: Vec<&str> = activities.iter().map(|a| a.id.as_str()).collect();
+    // Three activities share a timestamp across a page boundary, none of them
+    // may be lost or repeated
+    assert_eq!(ids.len(), 8);
+    assert_eq!(&ids[..3], ["stuck1", "deploy2", "deploy1"]);
+    assert_eq!(&ids[6..], ["push2", "push1"]);
+
+    let filter = ActivityFilter {
+        types: vec!["environment.push".to_string()],
+        result: Some("success".to_string()),
+        page_size: Some(1),
+        ..Default::default()
+    };
+    let activities = client
+        .activities("project1", Some("main"), &filter)
+        .await
+        .unwrap();
+    let ids: Vec<&str> = activities.iter().map(|a| a.id.as_str()).collect();
+    assert_eq!(ids, ["push4", "push1"]);
+
+    let filter = ActivityFilter {
+        after: Some("2024-05-03T10:00:00Z".parse().unwrap()),
+        before: Some("2024-05-03T23:00:00Z".parse().unwrap()),
+        ..Default::default()
+    };
+    let activities = client.activities("project1", None, &filter).await.unwrap();
+    assert_eq!(activities.len(), 3);
+}
+
+#[tokio::test]
+async fn waits_for_activity() {
+    let server = start(SEED).await;
+    let client = client(&server).await;
+
+    let activity = client
+        .wait_for_activity(
+            "project1",
+            "deploy1",
+            Duration::from_millis(10),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+    assert!(activity.is_success());
+    let polls = server
+        .requests()
+        .iter()
+        .filter(|r| r.ends_with("/activities/deploy1"))
+        .count();
+    assert_eq!(polls, 3);
+
+    let error = client
+        .wait_for_activity(
+            "project1",
+            "stuck1",
+            Duration::from_millis(10),
+            Duration::from_millis(50),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(error, platform::Error::ActivityTimeout { .. }));
+}
+
+#[tokio::test]
+async fn follows_activity_log() {
+    let server = start(SEED).await;
+    let client = client(&server).await;
+
+    let records: Vec<platform::ActivityLogRecord> = client
+        .activity_log_stream("project1", "deploy2")
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    let messages: Vec<&str> = records.iter().map(|r| r.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "Building application 'app'",
+            "  Installing dependencies ✓",
+            "Deploying",
+            ""
+        ]
+    );
+    assert!(records[3].seal);
+    assert!(records[0].timestamp.is_some());
+    // The first connection dropped after one record
+    assert!(server
+        .requests()
+        .contains(&"GET /projects/project1/activities/deploy2/log?start_at=1".to_string()));
+}
+
+#[tokio::test]
+async fn resumes_activity_log_after_a_dropped_connection() {
+    let server = start(
+        r#"
+organizations: []
+projects:
+  project1:
+    environments:
+      - { name: main, is_main: true }
+    activities:
+      - id: deploy1
+        type: environment.redeploy
+        environments: [main]
+        log: ["Building", "Deploying"]
+        log_abort_after: 1
+"#,
+    )
+    .await;
+    let client = client(&server).await;
+
+    let records: Vec<platform::ActivityLogRecord> = client
+        .activity_log_stream("project1", "deploy1")
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    let messages: Vec<&str> = records.iter().map(|r| r.message.as_str()).collect();
+    assert_eq!(messages, ["Building", "Deploying", ""]);
+    assert!(server
+        .requests()
+        .contains(&"GET /projects/project1/activities/deploy1/log?start_at=1".to_string()));
+}
+
+#[tokio::test]
+async fn reports_interrupted_activity_log() {
+    let server = start(
+        r#"
+organizations: []
+projects:
+  project1:
+    environments:
+      - { name: main, is_main: true }
+    activities:
+      - id: deploy1
+        type: environment.redeploy
+        environments: [main]
+        state: in_progress
+        result: null
+        log: ["Building", "Deploying"]
+"#,
+    )
+    .await;
+    let client = client(&server).await;
+
+    let records = client
+        .activity_log_stream("project1", "deploy1")
+        .await
+        .unwrap();
+    futures::pin_mut!(records);
+    assert_eq!(
+        records.try_next().await.unwrap().unwrap().message,
+        "Building"
+    );
+    assert_eq!(
+        records.try_next().await.unwrap().unwrap().message,
+        "Deploying"
+    );
+    // Closed without a seal, and reconnecting didn't bring anything new
+    match records.try_next().await {
+        Err(platform::Error::LogInterrupted { url, received }) => {
+            assert_eq!(url.query(), Some("start_at=2"));
+            assert_eq!(received, 2);
+        }
+        other => panic!("expected an interrupted log, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn manages_variables() {
+    let server = start(SEED).await;
+    let client = client(&server).await;
+
+    let mut secret = VariableRequest::new("env:SECRET", "hunter2");
+    secret.is_sensitive = true;
+    secret.visible_build = false;
+    client
+        .create_project_variable("project1", &secret)
+        .await
+        .unwrap();
+    let variables = client.project_variables("project1").await.unwrap();
+    assert_eq!(variables.len(), 1);
+    assert_eq!(variables[0].name, "env:SECRET");
+    assert!(variables[0].is_sensitive);
+    assert_eq!(variables[0].value, None);
+
+    secret.value = "correct horse".to_string();
+    secret.visible_build = true;
+    client
+        .update_project_variable("project1", &secret)
+        .await
+        .unwrap();
+    assert!(server
+        .requests()
+        .contains(&"PATCH /projects/project1/variables/env%3ASECRET".to_string()));
+    assert!(client.project_variables("project1").await.unwrap()[0].visible_build);
+
+    let error = client
+        .create_project_variable("project1", &secret)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(reqwest::StatusCode::CONFLICT));
+
+    client
+        .delete_project_variable("project1", "env:SECRET")
+        .await
+        .unwrap();
+    assert!(client
+        .project_variables("project1")
+        .await
+        .unwrap()
+        .is_empty());
+
+    let mut variable = VariableRequest::new("env:DEBUG", "1");
+    variable.is_inheritable = Some(false);
+    let activities = client
+        .create_environment_variable("project1", "staging", &variable)
+        .await
+        .unwrap();
+    assert_eq!(activities[0].r#type, "environment.variable.create");
+    let variables = client
+        .environment_variables("project1", "staging")
+        .await
+        .unwrap();
+    assert_eq!(variables[0].value.as_deref(), Some("1"));
+    assert!(!variables[0].is_inheritable);
+
+    variable.value = "0".to_string();
+    client
+        .update_environment_variable("project1", "staging", &variable)
+        .await
+        .unwrap();
+    client
+        .delete_environment_variable("project1", "staging", "env:DEBUG")
+        .await
+        .unwrap();
+    assert!(client
+        .delete_environment_variable("project1", "staging", "env:DEBUG")
+        .await
+        .unwrap_err()
+        .is_not_found());
+}
+
+const CONFIG_SEED: &str = r#"
+projects:
+  upsun:
+    environments:
+      - { name: main, is_main: true }
+    files:
+      .upsun/config.yaml: |
+        applications:
+          api:
+            type: php:8.3
+            source:
+              root: backend/
+          frontend:
+            type: nodejs:20
+            source:
+              root: /frontend
+        services:
+          db:
+            type: postgresql:16
+        routes:
+          "https://{default}/":
+            type: upstream
+            upstream: "frontend:http"
+      .upsun/workers.yaml: |
+        applications:
+          broken:
+            type: [php, "8.3"]
+      backend/composer.lock: "{}"
+  multi:
+    environments:
+      - { name: main, is_main: true }
+    files:
+      .platform/applications.yaml: |
+        - name: web
+          type: php:8.2
+          source:
+            root: web
+        - name: queue
+          type: python:3.12
+        - type: missing-name
+      .platform/services.yaml: "cache:\n  type: redis:7.2\n"
+      .platform/routes.yaml: "\"https://www.{default}/\":\n  type: upstream\n  upstream: web:http\n"
+  classic:
+    environments:
+      - { name: main, is_main: true }
+    files:
+      .platform.app.yaml: "name: root\ntype: php:8.1\n"
+      drupal/.platform.app.yaml: "name: drupal\ntype: php:7.4\n"
+      broken/.platform.app.yaml: "name: [unterminated\n"
+"#;
+
+async fn project_config(client: &ApiClient, project_id: &str) -> platform::ProjectConfig {
+    let environment = client.main_environment(project_id).await.unwrap();
+    let commit = client
+        .git_commit(project_id, environment.head_commit.as_deref().unwrap())
+        .await
+        .unwrap();
+    client
+        .project_config(project_id, &commit.tree)
+        .await
+        .unwrap()
+}
+
+fn apps(config: &platform::ProjectConfig) -> Vec<(&str, &str, &str)> {
+    let mut apps: Vec<_> = config
+        .apps
+        .iter()
+        .map(|a| (a.app.name.as_str(), a.root.as_str(), a.file.as_str()))
+        .collect();
+    apps.sort();
+    apps
+}
+
+#[tokio::test]
+async fn loads_project_config() {
+    let server = start(CONFIG_SEED).await;
+    let client = client(&server).await;
+
+    let upsun = project_config(&client, "upsun").await;
+    assert_eq!(
+        apps(&upsun),
+        [
+            ("api", "backend", ".upsun/config.yaml"),
+            ("frontend", "frontend", ".upsun/config.yaml"),
+        ]
+    );
+    assert_eq!(upsun.services["db"].r#type, "postgresql:16");
+    assert_eq!(upsun.routes["https://{default}/"].app(), Some("frontend"));
+    let resolved = upsun.resolve_routes("example.com", &[]);
+    assert_eq!(resolved[0].url, "https://example.com/");
+    assert_eq!(resolved[0].host(), "example.com");
+    assert_eq!(
+        upsun.unreadable,
+        [".upsun/workers.yaml#applications.broken"]
+    );
+
+    let multi = project_config(&client, "multi").await;
+    assert_eq!(
+        apps(&multi),
+        [
+            ("queue", "", ".platform/applications.yaml"),
+            ("web", "web", ".platform/applications.yaml"),
+        ]
+    );
+    assert_eq!(multi.services["cache"].r#type, "redis:7.2");
+    assert!(multi.routes.contains_key("https://www.{default}/"));
+    assert_eq!(multi.unreadable, [".platform/applications.yaml[2]"]);
+
+    let classic = project_config(&client, "classic").await;
+    assert_eq!(
+        apps(&classic),
+        [
+            ("drupal", "drupal", "drupal/.platform.app.yaml"),
+            ("root", "", ".platform.app.yaml"),
+        ]
+    );
+    assert!(classic.services.is_empty());
+    assert_eq!(classic.unreadable, ["broken/.platform.app.yaml"]);
+
+    let environment = client.main_environment("upsun").await.unwrap();
+    let commit = client
+        .git_commit("upsun", environment.head_commit.as_deref().unwrap())
+        .await
+        .unwrap();
+    let lock = client
+        .git_tree_lookup("upsun", &commit.tree, "backend/composer.lock")
+        .await
+        .unwrap();
+    assert_eq!(lock.unwrap().r#type, "blob");
+    let missing = client
+        .git_tree_lookup("upsun", &commit.tree, "backend/composer.lock/nope")
+        .await
+        .unwrap();
+    assert!(missing.is_none());
+}
+
+#[tokio::test]
+async fn keeps_loading_config_past_unreadable_blobs() {
+    let yaml = r#"
+organizations: []
+projects:
+  site:
+    environments:
+      - { name: main, is_main: true }
+    files:
+      .platform.app.yaml: "name: app\ntype: php:8.3\n"
+      .platform/services.yaml: "db:\n  type: mariadb:10.11\n"
+"#;
+    let server = start(yaml).await;
+    let api = client(&server).await;
+    let environment = api.main_environment("site").await.unwrap();
+    let commit = api
+        .git_commit("site", environment.head_commit.as_deref().unwrap())
+        .await
+        .unwrap();
+    let services = api
+        .git_tree_lookup("site", &commit.tree, ".platform/services.yaml")
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Same files, so the same blob, on a server failing to serve it
+    for (status, fatal) in [(400, false), (503, true)] {
+        let mut seed = Seed::from_yaml(yaml).unwrap();
+        seed.faults = vec![platform_mock::Fault {
+            path: format!("/projects/site/git/blobs/{}", services.sha),
+            status,
+            ..Default::default()
+        }];
+        let server = MockServer::start(seed).await;
+        let config = client(&server)
+            .await
+            .project_config("site", &commit.tree)
+            .await;
+        if fatal {
+            assert!(config.unwrap_err().is_unavailable());
+        } else {
+            let config = config.unwrap();
+            assert_eq!(apps(&config), [("app", "", ".platform.app.yaml")]);
+            assert!(config.services.is_empty());
+            assert_eq!(config.unreadable, [".platform/services.yaml"]);
+        }
+    }
+}
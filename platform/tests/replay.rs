@@ -0,0 +1,158 @@
+use platform::{ApiClient, Environment, EnvironmentVariable, RetryPolicy, Transport, Variable};
+use std::path::PathBuf;
+
+const PROJECT: &str = "abcdefgh12345";
+
+async fn client() -> ApiClient {
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/replay");
+
+    ApiClient::builder()
+        .transport(Transport::Replay(fixtures))
+        .retry_policy(RetryPolicy::none())
+        .build("not-a-real-token")
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn subscriptions_across_paged_organizations() {
+    let client = client().await;
+
+    let organizations = client.organizations().await.unwrap();
+    assert_eq!(organizations.len(), 2);
+    assert_eq!(organizations[1].name, "adapt-clients");
+
+    let subscriptions = client.subscriptions().await.unwrap();
+    let titles: Vec<&str> = subscriptions
+        .iter()
+        .map(|s| s.project_title.as_str())
+        .collect();
+    assert_eq!(titles, vec!["Client Site", "Intranet", "Other Agency"]);
+    assert_eq!(subscriptions[0].storage, 5120);
+    assert_eq!(
+        subscriptions[0].project_region.as_deref(),
+        Some("eu-3.platform.sh")
+    );
+}
+
+#[tokio::test]
+async fn main_environment() {
+    let client = client().await;
+
+    let environment = client.main_environment(PROJECT).await.unwrap();
+    assert_eq!(environment.name, "main");
+    assert_eq!(
+        environment.head_commit.as_deref(),
+        Some("c0ffee0000000000000000000000000000000001")
+    );
+    assert!(environment.last_backup_at.is_some());
+}
+
+#[tokio::test]
+async fn forbidden_project() {
+    let client = client().await;
+
+    let error = client
+        .get_json::<Vec<Environment>>("projects/denieddenied1/environments".to_string())
+        .await
+        .unwrap_err();
+    assert!(error.is_forbidden());
+    assert!(!error.is_unavailable());
+    assert!(error.to_string().contains("You do not have access"));
+}
+
+#[tokio::test]
+async fn scan_flow() {
+    let client = client().await;
+
+    let commit = client
+        .git_commit(PROJECT, "c0ffee0000000000000000000000000000000001")
+        .await
+        .unwrap();
+    let items = client
+        .git_tree_find(
+            PROJECT,
+            &commit.tree,
+            |path| path == ".platform.app.yaml" || path == "composer.lock",
+            2,
+            "".to_string(),
+        )
+        .await
+        .unwrap();
+    let paths: Vec<&str> = items.iter().map(|x| x.fullpath.as_str()).collect();
+    assert_eq!(paths, vec!["/.platform.app.yaml", "/composer.lock"]);
+
+    let app_yaml = client
+        .git_blob_decode(PROJECT, &items[0].sha)
+        .await
+        .unwrap();
+    let app: platform::PlatformApp = serde_yaml::from_slice(&app_yaml).unwrap();
+    assert_eq!(app.name, "app");
+    assert_eq!(app.r#type, "php:8.2");
+
+    let services = client
+        .git_tree_lookup_path(PROJECT, &commit.tree, ".platform")
+        .await
+        .unwrap()
+        .unwrap();
+    let services_yaml = client
+        .git_tree_lookup_path(PROJECT, &services.sha, "services.yaml")
+        .await
+        .unwrap()
+        .unwrap();
+    let services: std::collections::HashMap<String, platform::PlatformService> =
+        serde_yaml::from_slice(
+            &client
+                .git_blob_decode(PROJECT, &services_yaml.sha)
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(services["db"].r#type, "mariadb:10.6");
+}
+
+#[tokio::test]
+async fn copy_flow_variables() {
+    let client = client().await;
+
+    let variables: Vec<Variable> = client
+        .get_json(format!("projects/{}/variables", PROJECT))
+        .await
+        .unwrap();
+    assert_eq!(variables.len(), 2);
+    assert!(variables[0].is_sensitive);
+    assert_eq!(variables[0].value, None);
+    assert_eq!(variables[1].value.as_deref(), Some("256M"));
+
+    let variables: Vec<EnvironmentVariable> = client
+        .get_json(format!("projects/{}/environments/main/variables", PROJECT))
+        .await
+        .unwrap();
+    assert!(!variables[0].inherited);
+    assert!(variables[1].inherited);
+    assert_eq!(variables[0].environment, "main");
+}
+
+#[tokio::test]
+async fn activities() {
+    let client = client().await;
+
+    let activities: Vec<platform::Activity> = client
+        .get_json(format!("projects/{}/environments/main/activities", PROJECT))
+        .await
+        .unwrap();
+    assert_eq!(activities[0].r#type, "environment.push");
+    assert_eq!(activities[0].result.as_deref(), Some("success"));
+    assert_eq!(activities[0].timings["build"], 91.2);
+}
+
+#[tokio::test]
+async fn missing_fixture() {
+    let client = client().await;
+
+    let error = client
+        .get_json::<Vec<Environment>>("projects/unknown/environments".to_string())
+        .await
+        .unwrap_err();
+    assert!(matches!(error, platform::Error::MissingFixture(_)));
+}
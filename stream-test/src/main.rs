@@ -15,7 +15,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     for organization in organizations.iter() {
         let url = format!(
-            "https://api.platform.sh/organizations/{}/subscriptions",
+            "organizations/{}/subscriptions",
             organization.id
         );
         let stream = stream::unfold(Some(url), |state| async {
@@ -23,7 +23,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 None => None, // previous call was last page
                 Some(url) => {
                     info!(url);
-                    let result_buffer = client.get(url).send().await;
+                    let result_buffer = client.get(url).await;
                     match result_buffer {
                         Ok(buffer) => {
                             let result: Result<platform::Subscriptions, reqwest::Error> =